@@ -21,6 +21,12 @@
 //!         bme280,
 //!     )
 //!     .finalize(components::humidity_component_static!());
+//!     let pressure = components::pressure::PressureComponent::new(
+//!         board_kernel,
+//!         capsules_extra::pressure::DRIVER_NUM,
+//!         bme280,
+//!     )
+//!     .finalize(components::pressure_component_static!());
 //! ```
 
 use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
@@ -0,0 +1,81 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the environment snapshot capsule.
+//!
+//! Any of the sensors can be left out. The capsule becomes the client of the
+//! sensors it is given.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let environment_snapshot =
+//!     components::environment_snapshot::EnvironmentSnapshotComponent::new(
+//!         board_kernel,
+//!         capsules_extra::environment_snapshot::DRIVER_NUM,
+//!         Some(bme280),
+//!         Some(bme280),
+//!         Some(opt3001),
+//!     )
+//!     .finalize(components::environment_snapshot_component_static!());
+//! ```
+
+use capsules_extra::environment_snapshot::EnvironmentSnapshot;
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::sensors::{AmbientLight, HumidityDriver, TemperatureDriver};
+
+#[macro_export]
+macro_rules! environment_snapshot_component_static {
+    () => {{
+        kernel::static_buf!(capsules_extra::environment_snapshot::EnvironmentSnapshot<'static>)
+    };};
+}
+
+pub struct EnvironmentSnapshotComponent {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    temperature: Option<&'static dyn TemperatureDriver<'static>>,
+    humidity: Option<&'static dyn HumidityDriver<'static>>,
+    ambient_light: Option<&'static dyn AmbientLight<'static>>,
+}
+
+impl EnvironmentSnapshotComponent {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        temperature: Option<&'static dyn TemperatureDriver<'static>>,
+        humidity: Option<&'static dyn HumidityDriver<'static>>,
+        ambient_light: Option<&'static dyn AmbientLight<'static>>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            temperature,
+            humidity,
+            ambient_light,
+        }
+    }
+}
+
+impl Component for EnvironmentSnapshotComponent {
+    type StaticInput = &'static mut MaybeUninit<EnvironmentSnapshot<'static>>;
+    type Output = &'static EnvironmentSnapshot<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let environment_snapshot = s.write(EnvironmentSnapshot::new(
+            self.temperature,
+            self.humidity,
+            self.ambient_light,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+        environment_snapshot.set_sensor_clients();
+
+        environment_snapshot
+    }
+}
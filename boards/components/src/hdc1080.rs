@@ -0,0 +1,107 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the HDC1080 Temperature/Humidity Sensor.
+//!
+//! I2C Interface
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let hdc1080 = components::hdc1080::Hdc1080Component::new(
+//!     mux_i2c,
+//!     capsules_extra::hdc1080::BASE_ADDR,
+//!     mux_alarm,
+//! )
+//! .finalize(components::hdc1080_component_static!(
+//!     nrf52::rtc::Rtc<'static>,
+//!     nrf52840::i2c::TWI
+//! ));
+//! let temperature = components::temperature::TemperatureComponent::new(board_kernel, capsules_extra::temperature::DRIVER_NUM, hdc1080).finalize(components::temperature_component_static!(Hdc1080Type));
+//! let humidity = components::humidity::HumidityComponent::new(board_kernel, capsules_extra::humidity::DRIVER_NUM, hdc1080).finalize(components::humidity_component_static!(Hdc1080Type));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::hdc1080::{Hdc1080, BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::i2c;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! hdc1080_component_static {
+    ($A:ty, $I:ty $(,)?) => {{
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let hdc1080_alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let buffer = kernel::static_buf!([u8; capsules_extra::hdc1080::BUF_LEN]);
+        let hdc1080 = kernel::static_buf!(
+            capsules_extra::hdc1080::Hdc1080<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (hdc1080_alarm, i2c_device, buffer, hdc1080)
+    };};
+}
+
+pub type Hdc1080ComponentType<A, I> = Hdc1080<'static, A, I>;
+
+pub struct Hdc1080Component<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+}
+
+impl<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Hdc1080Component<A, I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+    ) -> Self {
+        Hdc1080Component {
+            i2c_mux,
+            i2c_address,
+            alarm_mux,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Component
+    for Hdc1080Component<A, I>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<
+            Hdc1080<'static, VirtualMuxAlarm<'static, A>, I2CDevice<'static, I>>,
+        >,
+    );
+    type Output = &'static Hdc1080<'static, VirtualMuxAlarm<'static, A>, I2CDevice<'static, I>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let hdc1080_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        hdc1080_alarm.setup();
+
+        let hdc1080_i2c = static_buffer
+            .1
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = static_buffer.2.write([0; BUF_LEN]);
+
+        let hdc1080 = static_buffer
+            .3
+            .write(Hdc1080::new(hdc1080_i2c, hdc1080_alarm, buffer));
+        hdc1080_i2c.set_client(hdc1080);
+        hdc1080_alarm.set_alarm_client(hdc1080);
+
+        hdc1080
+    }
+}
@@ -0,0 +1,100 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for detecting which I2C sensors are fitted at boot.
+//!
+//! The component creates an I2C device on the mux for each sensor in the
+//! table, and starts probing for the sensors. The returned registry can be
+//! passed to the temperature, humidity and ambient light components.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sensor_registry = components::i2c_sensor_registry::I2CSensorRegistryComponent::new(
+//!     mux_i2c,
+//!     [
+//!         Sensor::opt3001(0x44, opt3001),
+//!         Sensor::sht3x(0x44, sht3x),
+//!         Sensor::bme280(0x76, bme280),
+//!     ],
+//! )
+//! .finalize(components::i2c_sensor_registry_component_static!(
+//!     nrf52840::i2c::TWI,
+//!     3
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::i2c_sensor_registry::{I2CSensorRegistry, Sensor, SensorEntry, BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::i2c;
+
+#[macro_export]
+macro_rules! i2c_sensor_registry_component_static {
+    ($I:ty, $N:expr $(,)?) => {{
+        let i2c_devices = kernel::static_buf!(
+            [capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>; $N]
+        );
+        let sensors = kernel::static_buf!(
+            [capsules_extra::i2c_sensor_registry::SensorEntry<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >; $N]
+        );
+        let buffer = kernel::static_buf!([u8; capsules_extra::i2c_sensor_registry::BUF_LEN]);
+        let registry = kernel::static_buf!(
+            capsules_extra::i2c_sensor_registry::I2CSensorRegistry<
+                'static,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (i2c_devices, sensors, buffer, registry)
+    };};
+}
+
+pub type I2CSensorRegistryComponentType<I> = I2CSensorRegistry<'static, I2CDevice<'static, I>>;
+
+pub struct I2CSensorRegistryComponent<I: 'static + i2c::I2CMaster<'static>, const N: usize> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    sensors: [Sensor<'static>; N],
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>, const N: usize> I2CSensorRegistryComponent<I, N> {
+    pub fn new(i2c_mux: &'static MuxI2C<'static, I>, sensors: [Sensor<'static>; N]) -> Self {
+        I2CSensorRegistryComponent { i2c_mux, sensors }
+    }
+}
+
+impl<I: 'static + i2c::I2CMaster<'static>, const N: usize> Component
+    for I2CSensorRegistryComponent<I, N>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<[I2CDevice<'static, I>; N]>,
+        &'static mut MaybeUninit<[SensorEntry<'static, I2CDevice<'static, I>>; N]>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<I2CSensorRegistryComponentType<I>>,
+    );
+    type Output = &'static I2CSensorRegistryComponentType<I>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let i2c_devices: &'static [I2CDevice<'static, I>; N] =
+            s.0.write(core::array::from_fn(|i| {
+                I2CDevice::new(self.i2c_mux, self.sensors[i].address)
+            }));
+        let sensors = s.1.write(core::array::from_fn(|i| {
+            SensorEntry::new(self.sensors[i], &i2c_devices[i])
+        }));
+        let buffer = s.2.write([0; BUF_LEN]);
+
+        let registry = s.3.write(I2CSensorRegistry::new(sensors, buffer));
+        for i2c_device in i2c_devices.iter() {
+            i2c_device.set_client(registry);
+        }
+        let _ = registry.probe();
+
+        registry
+    }
+}
@@ -0,0 +1,249 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component to answer ICMPv6 Echo Requests (`ping6`) in the kernel.
+//!
+//! This provides one Component, ICMP6EchoComponent. It hooks the responder
+//! into the IPv6 receive path set up by UDPMuxComponent: the responder becomes
+//! the client of the IPv6 receiver and passes all packets it does not answer
+//! on to the UDP receive mux. Replies are sent through a dedicated IPv6
+//! sender on its own MacUser, as the responder has to be the sole client of
+//! its sender.
+//!
+//! Usage
+//! -----
+//! ```rust
+//!    let (udp_send_mux, udp_recv_mux, udp_port_table, ip_receive) = UDPMuxComponent::new(
+//!        ...
+//!    )
+//!    .finalize(components::udp_mux_component_static!(
+//!        nrf52840::rtc::Rtc,
+//!        Ieee802154MacDevice
+//!    ));
+//!
+//!    let icmp_echo = ICMP6EchoComponent::new(
+//!        mux_mac,
+//!        DEFAULT_CTX_PREFIX_LEN,
+//!        DEFAULT_CTX_PREFIX,
+//!        DST_MAC_ADDR,
+//!        src_mac_from_serial_num,
+//!        local_ip_ifaces,
+//!        mux_alarm,
+//!        ip_receive,
+//!        udp_recv_mux,
+//!    )
+//!    .finalize(components::icmpv6_echo_component_static!(
+//!        nrf52840::rtc::Rtc,
+//!        Ieee802154MacDevice
+//!    ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_extra::ieee802154::device::MacDevice;
+use capsules_extra::ieee802154::virtual_mac::{MacUser, MuxMac};
+use capsules_extra::net::icmpv6::icmpv6_echo::ICMP6EchoResponder;
+use capsules_extra::net::icmpv6::{ICMP6Header, ICMP6Type};
+use capsules_extra::net::ieee802154::MacAddress;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_recv::{IP6Receiver, IP6RecvClient, IP6RecvStruct};
+use capsules_extra::net::ipv6::ipv6_send::{IP6SendStruct, IP6Sender};
+use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
+use capsules_extra::net::network_capabilities::{
+    AddrRange, IpVisibilityCapability, NetworkCapability, PortRange,
+};
+use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::radio;
+use kernel::hil::time::Alarm;
+
+/// The maximum Echo Request data length that is answered. Longer requests
+/// are dropped.
+pub const MAX_ECHO_PAYLOAD_LEN: usize = 64;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! icmpv6_echo_component_static {
+    ($A:ty, $M:ty $(,)?) => {{
+        use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+        use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+        use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
+        use components::icmpv6_echo::MAX_ECHO_PAYLOAD_LEN;
+
+        let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let mac_user =
+            kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
+        let sixlowpan = kernel::static_buf!(
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, $A>,
+                sixlowpan_compression::Context,
+            >
+        );
+        let ip6_packet = kernel::static_buf!(capsules_extra::net::ipv6::IP6Packet<'static>);
+        let ip6_send = kernel::static_buf!(IP6SendStruct<'static, VirtualMuxAlarm<'static, $A>>);
+        let responder = kernel::static_buf!(
+            capsules_extra::net::icmpv6::icmpv6_echo::ICMP6EchoResponder<
+                'static,
+                IP6SendStruct<'static, VirtualMuxAlarm<'static, $A>>,
+            >
+        );
+
+        let radio_buf = kernel::static_buf!([u8; kernel::hil::radio::MAX_BUF_SIZE]);
+        let ip6_payload = kernel::static_buf!([u8; MAX_ECHO_PAYLOAD_LEN]);
+        let reply_buf = kernel::static_buf!([u8; MAX_ECHO_PAYLOAD_LEN]);
+
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let ip_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::IpVisibilityCapability);
+
+        (
+            alarm,
+            mac_user,
+            sixlowpan,
+            ip6_packet,
+            ip6_send,
+            responder,
+            radio_buf,
+            ip6_payload,
+            reply_buf,
+            net_cap,
+            ip_vis_cap,
+        )
+    };};
+}
+
+pub type ICMP6EchoComponentType<A> =
+    ICMP6EchoResponder<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>;
+
+pub struct ICMP6EchoComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> + 'static> {
+    mux_mac: &'static MuxMac<'static, M>,
+    ctx_pfix_len: u8,
+    ctx_pfix: [u8; 16],
+    dst_mac_addr: MacAddress,
+    src_mac_addr: MacAddress,
+    interface_list: &'static [IPAddr],
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    ip_receive: &'static IP6RecvStruct<'static>,
+    recv_client: &'static dyn IP6RecvClient,
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> ICMP6EchoComponent<A, M> {
+    pub fn new(
+        mux_mac: &'static MuxMac<'static, M>,
+        ctx_pfix_len: u8,
+        ctx_pfix: [u8; 16],
+        dst_mac_addr: MacAddress,
+        src_mac_addr: MacAddress,
+        interface_list: &'static [IPAddr],
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        ip_receive: &'static IP6RecvStruct<'static>,
+        recv_client: &'static dyn IP6RecvClient,
+    ) -> Self {
+        Self {
+            mux_mac,
+            ctx_pfix_len,
+            ctx_pfix,
+            dst_mac_addr,
+            src_mac_addr,
+            interface_list,
+            alarm_mux,
+            ip_receive,
+            recv_client,
+        }
+    }
+}
+
+impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for ICMP6EchoComponent<A, M> {
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<MacUser<'static, M>>,
+        &'static mut MaybeUninit<
+            sixlowpan_state::Sixlowpan<
+                'static,
+                VirtualMuxAlarm<'static, A>,
+                sixlowpan_compression::Context,
+            >,
+        >,
+        &'static mut MaybeUninit<IP6Packet<'static>>,
+        &'static mut MaybeUninit<IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        &'static mut MaybeUninit<ICMP6EchoComponentType<A>>,
+        &'static mut MaybeUninit<[u8; radio::MAX_BUF_SIZE]>,
+        &'static mut MaybeUninit<[u8; MAX_ECHO_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<[u8; MAX_ECHO_PAYLOAD_LEN]>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<IpVisibilityCapability>,
+    );
+    type Output = &'static ICMP6EchoComponentType<A>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let virtual_alarm = s.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        virtual_alarm.setup();
+
+        // Only used to transmit replies, incoming frames are received through
+        // the MacUser of the UDP stack.
+        let mac_user = s.1.write(MacUser::new(self.mux_mac));
+        self.mux_mac.add_user(mac_user);
+
+        let create_cap = create_capability!(capabilities::NetworkCapabilityCreationCapability);
+        let net_cap = s.9.write(NetworkCapability::new(
+            AddrRange::Any,
+            PortRange::Any,
+            PortRange::Any,
+            &create_cap,
+        ));
+        let ip_vis = s.10.write(IpVisibilityCapability::new(&create_cap));
+
+        let sixlowpan = s.2.write(sixlowpan_state::Sixlowpan::new(
+            sixlowpan_compression::Context {
+                prefix: self.ctx_pfix,
+                prefix_len: self.ctx_pfix_len,
+                id: 0,
+                compress: false,
+            },
+            // OK to reuse, as this Sixlowpan only transmits and only sets
+            // alarms while reassembling received packets
+            virtual_alarm,
+        ));
+        let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
+        let sixlowpan_tx = sixlowpan_state::TxState::new(sixlowpan_state);
+
+        let ip_pyld: IPPayload = IPPayload {
+            header: TransportHeader::ICMP(ICMP6Header::new(ICMP6Type::Type129)),
+            payload: s.7.write([0; MAX_ECHO_PAYLOAD_LEN]),
+        };
+        let ip6_dg = s.3.write(IP6Packet::new(ip_pyld));
+        let radio_buf = s.6.write([0; radio::MAX_BUF_SIZE]);
+
+        let ip_send = s.4.write(IP6SendStruct::new(
+            ip6_dg,
+            virtual_alarm,
+            radio_buf,
+            sixlowpan_tx,
+            mac_user,
+            self.dst_mac_addr,
+            self.src_mac_addr,
+            ip_vis,
+        ));
+        virtual_alarm.set_alarm_client(ip_send);
+        mac_user.set_transmit_client(ip_send);
+
+        let reply_buf = s.8.write([0; MAX_ECHO_PAYLOAD_LEN]);
+        let responder = s.5.write(ICMP6EchoResponder::new(
+            ip_send,
+            self.interface_list,
+            reply_buf,
+            net_cap,
+        ));
+        ip_send.set_client(responder);
+
+        self.ip_receive.set_client(responder);
+        responder.set_client(self.recv_client);
+
+        responder
+    }
+}
@@ -29,6 +29,7 @@ pub mod dac;
 pub mod date_time;
 pub mod debug_queue;
 pub mod debug_writer;
+pub mod environment_snapshot;
 pub mod eui64;
 pub mod flash;
 pub mod fm25cl;
@@ -36,11 +37,14 @@ pub mod ft6x06;
 pub mod fxos8700;
 pub mod gpio;
 pub mod hd44780;
+pub mod hdc1080;
 pub mod hmac;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
 pub mod i2c;
+pub mod i2c_sensor_registry;
+pub mod icmpv6_echo;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod keyboard_hid;
@@ -59,8 +63,10 @@ pub mod ltc294x;
 pub mod mlx90614;
 pub mod mx25r6435f;
 pub mod ninedof;
+pub mod nonvolatile_counters;
 pub mod nonvolatile_storage;
 pub mod nrf51822;
+pub mod opt3001;
 pub mod panic_button;
 pub mod pressure;
 pub mod process_console;
@@ -72,6 +78,7 @@ pub mod rng;
 pub mod sched;
 pub mod screen;
 pub mod segger_rtt;
+pub mod sensor_records;
 pub mod sh1106;
 pub mod sha;
 pub mod sht3x;
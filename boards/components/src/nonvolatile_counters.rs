@@ -0,0 +1,86 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for counters kept in nonvolatile storage.
+//!
+//! This provides one component, NonvolatileCountersComponent, which keeps a
+//! boot counter and a frame counter on top of a `NonvolatileStorage`
+//! implementation, such as `NonvolatileToPages`. The storage must not be
+//! shared with other clients.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let nv_counters = components::nonvolatile_counters::NonvolatileCountersComponent::new(
+//!     nv_to_pages,
+//!     0x3E000,
+//!     0x1000,
+//! )
+//! .finalize(components::nonvolatile_counters_component_static!(
+//!     capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, F>
+//! ));
+//! nv_counters.init();
+//! ```
+
+use capsules_extra::nonvolatile_counters::{NonvolatileCounters, RECORD_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::nonvolatile_storage::NonvolatileStorage;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! nonvolatile_counters_component_static {
+    ($S:ty $(,)?) => {{
+        let buffer = kernel::static_buf!([u8; capsules_extra::nonvolatile_counters::RECORD_LEN]);
+        let counters = kernel::static_buf!(
+            capsules_extra::nonvolatile_counters::NonvolatileCounters<'static, $S>
+        );
+
+        (buffer, counters)
+    };};
+}
+
+pub type NonvolatileCountersComponentType<S> = NonvolatileCounters<'static, S>;
+
+pub struct NonvolatileCountersComponent<S: 'static + NonvolatileStorage<'static>> {
+    storage: &'static S,
+    start_address: usize,
+    slot_size: usize,
+}
+
+impl<S: 'static + NonvolatileStorage<'static>> NonvolatileCountersComponent<S> {
+    pub fn new(
+        storage: &'static S,
+        start_address: usize,
+        slot_size: usize,
+    ) -> NonvolatileCountersComponent<S> {
+        NonvolatileCountersComponent {
+            storage,
+            start_address,
+            slot_size,
+        }
+    }
+}
+
+impl<S: 'static + NonvolatileStorage<'static>> Component for NonvolatileCountersComponent<S> {
+    type StaticInput = (
+        &'static mut MaybeUninit<[u8; RECORD_LEN]>,
+        &'static mut MaybeUninit<NonvolatileCounters<'static, S>>,
+    );
+    type Output = &'static NonvolatileCounters<'static, S>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let buffer = static_buffer.0.write([0; RECORD_LEN]);
+
+        let counters = static_buffer.1.write(NonvolatileCounters::new(
+            self.storage,
+            self.start_address,
+            self.slot_size,
+            buffer,
+        ));
+        self.storage.set_client(counters);
+
+        counters
+    }
+}
@@ -0,0 +1,123 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the OPT3001 ambient light sensor.
+//!
+//! I2C Interface, with an optional interrupt pin connected to the sensor's
+//! INT output.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let opt3001 = components::opt3001::Opt3001Component::new(
+//!     mux_i2c,
+//!     capsules_extra::opt3001::BASE_ADDR,
+//!     mux_alarm,
+//!     Some(&nrf52840_peripherals.gpio_port[OPT3001_INT_PIN]),
+//! )
+//! .finalize(components::opt3001_component_static!(
+//!     nrf52::rtc::Rtc<'static>,
+//!     nrf52840::i2c::TWI
+//! ));
+//! let ambient_light = components::isl29035::AmbientLightComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ambient_light::DRIVER_NUM,
+//!     opt3001,
+//! )
+//! .finalize(components::ambient_light_component_static!());
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+use capsules_core::virtualizers::virtual_i2c::{I2CDevice, MuxI2C};
+use capsules_extra::opt3001::{Opt3001, BUF_LEN};
+use core::mem::MaybeUninit;
+use kernel::component::Component;
+use kernel::hil::gpio;
+use kernel::hil::i2c;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! opt3001_component_static {
+    ($A:ty, $I:ty $(,)?) => {{
+        let alarm = kernel::static_buf!(
+            capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>
+        );
+        let i2c_device =
+            kernel::static_buf!(capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>);
+        let i2c_buffer = kernel::static_buf!([u8; capsules_extra::opt3001::BUF_LEN]);
+        let opt3001 = kernel::static_buf!(
+            capsules_extra::opt3001::Opt3001<
+                'static,
+                capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                capsules_core::virtualizers::virtual_i2c::I2CDevice<'static, $I>,
+            >
+        );
+
+        (alarm, i2c_device, i2c_buffer, opt3001)
+    };};
+}
+
+pub type Opt3001ComponentType<A, I> = Opt3001<'static, A, I>;
+
+pub struct Opt3001Component<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> {
+    i2c_mux: &'static MuxI2C<'static, I>,
+    i2c_address: u8,
+    alarm_mux: &'static MuxAlarm<'static, A>,
+    interrupt_pin: Option<&'static dyn gpio::InterruptPin<'static>>,
+}
+
+impl<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Opt3001Component<A, I> {
+    pub fn new(
+        i2c_mux: &'static MuxI2C<'static, I>,
+        i2c_address: u8,
+        alarm_mux: &'static MuxAlarm<'static, A>,
+        interrupt_pin: Option<&'static dyn gpio::InterruptPin<'static>>,
+    ) -> Self {
+        Opt3001Component {
+            i2c_mux,
+            i2c_address,
+            alarm_mux,
+            interrupt_pin,
+        }
+    }
+}
+
+impl<A: 'static + Alarm<'static>, I: 'static + i2c::I2CMaster<'static>> Component
+    for Opt3001Component<A, I>
+{
+    type StaticInput = (
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
+        &'static mut MaybeUninit<I2CDevice<'static, I>>,
+        &'static mut MaybeUninit<[u8; BUF_LEN]>,
+        &'static mut MaybeUninit<
+            Opt3001<'static, VirtualMuxAlarm<'static, A>, I2CDevice<'static, I>>,
+        >,
+    );
+    type Output = &'static Opt3001<'static, VirtualMuxAlarm<'static, A>, I2CDevice<'static, I>>;
+
+    fn finalize(self, static_buffer: Self::StaticInput) -> Self::Output {
+        let opt3001_alarm = static_buffer.0.write(VirtualMuxAlarm::new(self.alarm_mux));
+        opt3001_alarm.setup();
+
+        let opt3001_i2c = static_buffer
+            .1
+            .write(I2CDevice::new(self.i2c_mux, self.i2c_address));
+        let buffer = static_buffer.2.write([0; BUF_LEN]);
+
+        let opt3001 = static_buffer.3.write(Opt3001::new(
+            opt3001_i2c,
+            opt3001_alarm,
+            self.interrupt_pin,
+            buffer,
+        ));
+        opt3001_i2c.set_client(opt3001);
+        opt3001_alarm.set_alarm_client(opt3001);
+        if let Some(pin) = self.interrupt_pin {
+            pin.set_client(opt3001);
+        }
+
+        opt3001
+    }
+}
@@ -0,0 +1,183 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Component for the sensor record batching capsule.
+//!
+//! Batches are appended to `log` if it is given, and sent over UDP if an
+//! [`UploadConfig`] is given. The component binds `src_port` for the uploads.
+//!
+//! Usage
+//! -----
+//! ```rust
+//! let sensor_records = components::sensor_records::SensorRecordsComponent::new(
+//!     board_kernel,
+//!     capsules_extra::sensor_records::DRIVER_NUM,
+//!     Some(log),
+//!     Some(components::sensor_records::UploadConfig {
+//!         udp_send_mux,
+//!         port_table: udp_port_table,
+//!         src_port: 16124,
+//!         dest: collector_addr,
+//!         dst_port: 16124,
+//!     }),
+//! )
+//! .finalize(components::sensor_records_component_static!(
+//!     nrf52840::rtc::Rtc
+//! ));
+//! ```
+
+use capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm;
+use capsules_extra::net::ipv6::ip_utils::IPAddr;
+use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
+use capsules_extra::net::network_capabilities::{
+    AddrRange, NetworkCapability, PortRange, UdpVisibilityCapability,
+};
+use capsules_extra::net::udp::udp_port_table::UdpPortManager;
+use capsules_extra::net::udp::udp_send::{MuxUdpSender, UDPSendStruct, UDPSender};
+use capsules_extra::sensor_records::{SensorRecords, UdpUpload, BATCH_LEN};
+use core::mem::MaybeUninit;
+use kernel::capabilities;
+use kernel::capabilities::NetworkCapabilityCreationCapability;
+use kernel::component::Component;
+use kernel::create_capability;
+use kernel::hil::log::LogWrite;
+use kernel::hil::time::Alarm;
+
+// Setup static space for the objects.
+#[macro_export]
+macro_rules! sensor_records_component_static {
+    ($A:ty $(,)?) => {{
+        let udp_send = kernel::static_buf!(
+            capsules_extra::net::udp::udp_send::UDPSendStruct<
+                'static,
+                capsules_extra::net::ipv6::ipv6_send::IP6SendStruct<
+                    'static,
+                    capsules_core::virtualizers::virtual_alarm::VirtualMuxAlarm<'static, $A>,
+                >,
+            >
+        );
+        let udp_vis_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::UdpVisibilityCapability);
+        let net_cap =
+            kernel::static_buf!(capsules_extra::net::network_capabilities::NetworkCapability);
+        let batch_buffer = kernel::static_buf!([u8; capsules_extra::sensor_records::BATCH_LEN]);
+        let flush_buffer = kernel::static_buf!([u8; capsules_extra::sensor_records::BATCH_LEN]);
+        let sensor_records =
+            kernel::static_buf!(capsules_extra::sensor_records::SensorRecords<'static>);
+
+        (
+            udp_send,
+            udp_vis_cap,
+            net_cap,
+            batch_buffer,
+            flush_buffer,
+            sensor_records,
+        )
+    };};
+}
+
+/// Where and how to upload batches over UDP.
+pub struct UploadConfig<A: Alarm<'static> + 'static> {
+    pub udp_send_mux:
+        &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+    pub port_table: &'static UdpPortManager,
+    pub src_port: u16,
+    pub dest: IPAddr,
+    pub dst_port: u16,
+}
+
+pub struct SensorRecordsComponent<A: Alarm<'static> + 'static> {
+    board_kernel: &'static kernel::Kernel,
+    driver_num: usize,
+    log: Option<&'static dyn LogWrite<'static>>,
+    upload: Option<UploadConfig<A>>,
+}
+
+impl<A: Alarm<'static>> SensorRecordsComponent<A> {
+    pub fn new(
+        board_kernel: &'static kernel::Kernel,
+        driver_num: usize,
+        log: Option<&'static dyn LogWrite<'static>>,
+        upload: Option<UploadConfig<A>>,
+    ) -> Self {
+        Self {
+            board_kernel,
+            driver_num,
+            log,
+            upload,
+        }
+    }
+}
+
+impl<A: Alarm<'static>> Component for SensorRecordsComponent<A> {
+    type StaticInput = (
+        &'static mut MaybeUninit<
+            UDPSendStruct<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
+        >,
+        &'static mut MaybeUninit<UdpVisibilityCapability>,
+        &'static mut MaybeUninit<NetworkCapability>,
+        &'static mut MaybeUninit<[u8; BATCH_LEN]>,
+        &'static mut MaybeUninit<[u8; BATCH_LEN]>,
+        &'static mut MaybeUninit<SensorRecords<'static>>,
+    );
+    type Output = &'static SensorRecords<'static>;
+
+    fn finalize(self, s: Self::StaticInput) -> Self::Output {
+        let grant_cap = create_capability!(capabilities::MemoryAllocationCapability);
+
+        let (udp_send, udp_upload) = match self.upload {
+            Some(upload) => {
+                let create_cap = create_capability!(NetworkCapabilityCreationCapability);
+                let udp_vis = s.1.write(UdpVisibilityCapability::new(&create_cap));
+                let udp_send: &_ = s.0.write(UDPSendStruct::new(upload.udp_send_mux, udp_vis));
+                let net_cap = s.2.write(NetworkCapability::new(
+                    AddrRange::Addr(upload.dest),
+                    PortRange::Port(upload.src_port),
+                    PortRange::Port(upload.dst_port),
+                    &create_cap,
+                ));
+
+                // Panics if there is no free socket or the port is taken, as the
+                // uploads could not be sent otherwise.
+                let socket = upload.port_table.create_socket().unwrap();
+                let (tx_bind, _rx_bind) = upload
+                    .port_table
+                    .bind(socket, upload.src_port, net_cap)
+                    .unwrap();
+                udp_send.set_binding(tx_bind);
+
+                (
+                    Some(udp_send),
+                    Some(UdpUpload::new(
+                        udp_send,
+                        upload.dest,
+                        upload.dst_port,
+                        net_cap,
+                    )),
+                )
+            }
+            None => (None, None),
+        };
+
+        let batch_buffer = s.3.write([0; BATCH_LEN]);
+        let flush_buffer = s.4.write([0; BATCH_LEN]);
+
+        let sensor_records = s.5.write(SensorRecords::new(
+            self.log,
+            udp_upload,
+            batch_buffer,
+            flush_buffer,
+            self.board_kernel.create_grant(self.driver_num, &grant_cap),
+        ));
+
+        if let Some(log) = self.log {
+            log.set_append_client(sensor_records);
+        }
+        if let Some(udp_send) = udp_send {
+            udp_send.set_client(sensor_records);
+        }
+
+        sensor_records
+    }
+}
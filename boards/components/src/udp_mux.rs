@@ -6,12 +6,14 @@
 //!
 //! This provides one Component, UDPMuxComponent. This component
 //! exposes a MuxUdpSender that other components can implement
-//! UDPSenders on top of to use the UDP/6Lowpan stack.
+//! UDPSenders on top of to use the UDP/6Lowpan stack. It also returns the
+//! IPv6 receiver, so that other IPv6 receive clients (such as the ICMPv6 echo
+//! responder) can be placed in front of the UDP receive mux.
 //!
 //! Usage
 //! -----
 //! ```rust
-//!    let (udp_mux, udp_recv) = UDPMuxComponent::new(
+//!    let (udp_mux, udp_recv, udp_port_table, ip_receive) = UDPMuxComponent::new(
 //!        mux_mac,
 //!        DEFAULT_CTX_PREFIX_LEN,
 //!        DEFAULT_CTX_PREFIX,
@@ -19,10 +21,14 @@
 //!        src_mac_from_serial_num,
 //!        local_ip_ifaces,
 //!        mux_alarm,
-//!        MAX_PAYLOAD_LEN,
+//!        EvictionPolicy::DropNew,
 //!    )
 //!    .finalize(components::udp_mux_component_static!());
 //! ```
+//!
+//! The eviction policy decides what happens to a newly received packet when
+//! the 6LoWPAN layer is still reassembling another one: `DropNew` drops the
+//! new packet, `EvictOldest` aborts the reassembly in progress instead.
 
 // Author: Hudson Ayers <hayers@stanford.edu>
 // Last Modified: 5/21/2019
@@ -37,6 +43,7 @@ use capsules_extra::net::ipv6::ipv6_send::IP6SendStruct;
 use capsules_extra::net::ipv6::ipv6_send::IP6Sender;
 use capsules_extra::net::ipv6::{IP6Packet, IPPayload, TransportHeader};
 use capsules_extra::net::network_capabilities::{IpVisibilityCapability, UdpVisibilityCapability};
+use capsules_extra::net::sixlowpan::sixlowpan_state::EvictionPolicy;
 use capsules_extra::net::sixlowpan::{sixlowpan_compression, sixlowpan_state};
 use capsules_extra::net::udp::udp_port_table::{
     SocketBindingEntry, UdpPortManager, MAX_NUM_BOUND_PORTS,
@@ -74,6 +81,7 @@ macro_rules! udp_mux_component_static {
         use core::mem::MaybeUninit;
 
         let alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
+        let sixlowpan_alarm = kernel::static_buf!(VirtualMuxAlarm<'static, $A>);
         let mac_user =
             kernel::static_buf!(capsules_extra::ieee802154::virtual_mac::MacUser<'static, $M>);
         let sixlowpan = kernel::static_buf!(
@@ -150,6 +158,7 @@ macro_rules! udp_mux_component_static {
             udp_dgram,
             udp_vis_cap,
             ip_vis_cap,
+            sixlowpan_alarm,
         )
     };};
 }
@@ -162,6 +171,7 @@ pub struct UDPMuxComponent<A: Alarm<'static> + 'static, M: MacDevice<'static> +
     src_mac_addr: MacAddress,
     interface_list: &'static [IPAddr],
     alarm_mux: &'static MuxAlarm<'static, A>,
+    eviction_policy: EvictionPolicy,
 }
 
 impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> UDPMuxComponent<A, M> {
@@ -173,6 +183,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> UDPMuxComponent<A, M> {
         src_mac_addr: MacAddress,
         interface_list: &'static [IPAddr],
         alarm_mux: &'static MuxAlarm<'static, A>,
+        eviction_policy: EvictionPolicy,
     ) -> Self {
         Self {
             mux_mac,
@@ -182,6 +193,7 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> UDPMuxComponent<A, M> {
             src_mac_addr,
             interface_list,
             alarm_mux,
+            eviction_policy,
         }
     }
 }
@@ -223,11 +235,13 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         &'static mut MaybeUninit<[u8; MAX_PAYLOAD_LEN]>,
         &'static mut MaybeUninit<UdpVisibilityCapability>,
         &'static mut MaybeUninit<IpVisibilityCapability>,
+        &'static mut MaybeUninit<VirtualMuxAlarm<'static, A>>,
     );
     type Output = (
         &'static MuxUdpSender<'static, IP6SendStruct<'static, VirtualMuxAlarm<'static, A>>>,
         &'static MuxUdpReceiver<'static>,
         &'static UdpPortManager,
+        &'static IP6RecvStruct<'static>,
     );
 
     fn finalize(self, s: Self::StaticInput) -> Self::Output {
@@ -243,6 +257,10 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
         let udp_vis = s.14.write(UdpVisibilityCapability::new(&create_cap));
         let ip_vis = s.15.write(IpVisibilityCapability::new(&create_cap));
 
+        // 6LoWPAN sets its own alarm to expire timed-out reassemblies
+        let sixlowpan_alarm = s.16.write(VirtualMuxAlarm::new(self.alarm_mux));
+        sixlowpan_alarm.setup();
+
         let sixlowpan = s.2.write(sixlowpan_state::Sixlowpan::new(
             sixlowpan_compression::Context {
                 prefix: self.ctx_pfix,
@@ -250,8 +268,10 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
                 id: 0,
                 compress: false,
             },
-            ipsender_virtual_alarm, // OK to reuse bc only used to get time, not set alarms
+            sixlowpan_alarm,
         ));
+        sixlowpan_alarm.set_alarm_client(sixlowpan);
+        sixlowpan.set_eviction_policy(self.eviction_policy);
 
         let sixlowpan_rx_buffer = s.12.write([0; 1280]);
         let sixlowpan_state = sixlowpan as &dyn sixlowpan_state::SixlowpanState;
@@ -315,6 +335,6 @@ impl<A: Alarm<'static> + 'static, M: MacDevice<'static>> Component for UDPMuxCom
             udp_vis,
         ));
 
-        (udp_send_mux, udp_recv_mux, udp_port_table)
+        (udp_send_mux, udp_recv_mux, udp_port_table, ip_receive)
     }
 }
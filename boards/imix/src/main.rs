@@ -672,20 +672,22 @@ pub unsafe fn main() {
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        src_mac_from_serial_num, //comment out for dual rx test only
-        //MacAddress::Short(49138), //comment in for dual rx test only
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(
-        sam4l::ast::Ast,
-        Ieee802154MacDevice
-    ));
+    let (udp_send_mux, udp_recv_mux, udp_port_table, _ip_receive) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            src_mac_from_serial_num, //comment out for dual rx test only
+            //MacAddress::Short(49138), //comment in for dual rx test only
+            local_ip_ifaces,
+            mux_alarm,
+            capsules_extra::net::sixlowpan::sixlowpan_state::EvictionPolicy::DropNew,
+        )
+        .finalize(components::udp_mux_component_static!(
+            sam4l::ast::Ast,
+            Ieee802154MacDevice
+        ));
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
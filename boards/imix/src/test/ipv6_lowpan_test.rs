@@ -177,6 +177,8 @@ pub unsafe fn initialize_all(
         )
     );
 
+    sixlo_alarm.set_alarm_client(sixlowpan);
+
     let sixlowpan_state = sixlowpan as &dyn SixlowpanState;
     let sixlowpan_tx = TxState::new(sixlowpan_state);
 
@@ -577,19 +577,21 @@ pub unsafe fn start() -> (
         ]
     );
 
-    let (udp_send_mux, udp_recv_mux, udp_port_table) = components::udp_mux::UDPMuxComponent::new(
-        mux_mac,
-        DEFAULT_CTX_PREFIX_LEN,
-        DEFAULT_CTX_PREFIX,
-        DST_MAC_ADDR,
-        MacAddress::Short(device_id_bottom_16),
-        local_ip_ifaces,
-        mux_alarm,
-    )
-    .finalize(components::udp_mux_component_static!(
-        nrf52840::rtc::Rtc,
-        Ieee802154MacDevice
-    ));
+    let (udp_send_mux, udp_recv_mux, udp_port_table, _ip_receive) =
+        components::udp_mux::UDPMuxComponent::new(
+            mux_mac,
+            DEFAULT_CTX_PREFIX_LEN,
+            DEFAULT_CTX_PREFIX,
+            DST_MAC_ADDR,
+            MacAddress::Short(device_id_bottom_16),
+            local_ip_ifaces,
+            mux_alarm,
+            capsules_extra::net::sixlowpan::sixlowpan_state::EvictionPolicy::DropNew,
+        )
+        .finalize(components::udp_mux_component_static!(
+            nrf52840::rtc::Rtc,
+            Ieee802154MacDevice
+        ));
 
     // UDP driver initialization happens here
     let udp_driver = components::udp_driver::UDPDriverComponent::new(
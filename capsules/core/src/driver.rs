@@ -57,6 +57,7 @@ pub enum NUM {
     NvmStorage            = 0x50001,
     SdCard                = 0x50002,
     Kv                    = 0x50003,
+    SensorRecords         = 0x50004,
 
     // Sensors
     Temperature           = 0x60000,
@@ -67,6 +68,8 @@ pub enum NUM {
     SoundPressure         = 0x60006,
     AirQuality            = 0x60007,
     Pressure              = 0x60008,
+    EnvironmentSnapshot   = 0x60009,
+    I2CSensorRegistry     = 0x6000A,
 
     // Sensor ICs
     Tsl2561               = 0x70000,
@@ -0,0 +1,850 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Virtualize the Alarm interface with a hierarchical timer wheel.
+//!
+//! `MuxAlarmWheel` is an alternative to
+//! [`MuxAlarm`](super::virtual_alarm::MuxAlarm) for boards with many
+//! concurrently armed alarms, such as network retransmission timers.
+//! `MuxAlarm` looks at every virtual alarm whenever the underlying alarm
+//! fires. The wheel instead sorts armed alarms into slots by expiration time,
+//! so an interrupt only looks at the alarms in the slots which have come
+//! due. Setting and disarming an alarm takes time proportional to the number
+//! of alarms in its slot.
+//!
+//! The wheel has [`LEVELS`] levels of 16 slots. A slot of level `n` covers
+//! `16^n` ticks, so the wheel covers `16^LEVELS` ticks ahead of the current
+//! time. Alarms which expire later are placed in the top level and sorted
+//! again when their slot comes due. When a slot of a higher level comes due,
+//! its alarms are moved down to the lower levels, until they fire from
+//! level 0, which has a resolution of one tick.
+//!
+//! Expiration times are kept as 64 bit counts of ticks, which are extended
+//! from the underlying alarm's counter. For this, the underlying alarm is
+//! never set more than half of its range ahead.
+//!
+//! [`VirtualWheelAlarm`] implements `Alarm` in the same way as
+//! `VirtualMuxAlarm`, so clients can be used with either. As with
+//! `MuxAlarm`, alarms which are set from an alarm callback fire at the
+//! earliest in the next interrupt.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let mux_alarm = static_init!(MuxAlarmWheel<'static, Rtc>, MuxAlarmWheel::new(rtc));
+//! rtc.set_alarm_client(mux_alarm);
+//!
+//! let virtual_alarm = static_init!(
+//!     VirtualWheelAlarm<'static, Rtc>,
+//!     VirtualWheelAlarm::new(mux_alarm)
+//! );
+//! virtual_alarm.setup();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::time::{self, Alarm, Ticks, Time};
+use kernel::utilities::cells::OptionalCell;
+use kernel::ErrorCode;
+
+/// Number of levels of the wheel.
+pub const LEVELS: usize = 6;
+
+const SLOT_BITS: u32 = 4;
+const SLOTS: usize = 1 << SLOT_BITS;
+const SLOT_MASK: u64 = SLOTS as u64 - 1;
+
+/// Number of ticks covered by the wheel.
+const SPAN: u64 = 1 << (SLOT_BITS * LEVELS as u32);
+
+/// Converts ticks to a `u64`. `Ticks::into_u32` truncates 64 bit ticks, so
+/// the upper bits are taken separately. This saturates for 64 bit ticks of
+/// 2^63 and above, which a counter does not reach in practice.
+fn ticks_to_u64<T: Ticks>(ticks: T) -> u64 {
+    ((ticks.saturating_scale(1, 1 << 31) as u64) << 31) | (ticks.into_u32() & 0x7FFF_FFFF) as u64
+}
+
+/// Returns the level at which an alarm expiring at `expiration` is placed.
+fn level_for(current: u64, expiration: u64) -> usize {
+    // Alarms which are due are placed in level 0, and alarms further out
+    // than the wheel covers in the top level.
+    let masked = core::cmp::min((current ^ expiration) | SLOT_MASK, SPAN - 1);
+    let significant = u64::BITS - 1 - masked.leading_zeros();
+    (significant / SLOT_BITS) as usize
+}
+
+/// Returns the slot of `level` into which an alarm expiring at `expiration`
+/// is placed.
+fn slot_for(level: usize, expiration: u64) -> usize {
+    ((expiration >> (level as u32 * SLOT_BITS)) & SLOT_MASK) as usize
+}
+
+/// Which list an alarm is linked into.
+#[derive(Copy, Clone, PartialEq)]
+enum Position {
+    /// Not armed.
+    Idle,
+    /// In a slot of the wheel, given as level and slot.
+    Wheel(usize, usize),
+    /// Taken out of a slot which is being processed.
+    Processing,
+    /// Set from an alarm callback, to be placed in the wheel once all due
+    /// alarms have fired.
+    Deferred,
+}
+
+/// A virtual alarm of a [`MuxAlarmWheel`].
+pub struct VirtualWheelAlarm<'a, A: Alarm<'a>> {
+    mux: &'a MuxAlarmWheel<'a, A>,
+    /// Reference and dt passed to `set_alarm`.
+    reference: Cell<A::Ticks>,
+    dt: Cell<A::Ticks>,
+    /// Expiration time in the wheel's time base.
+    expiration: Cell<u64>,
+    position: Cell<Position>,
+    /// Reference to this alarm, to link it into the wheel.
+    this: OptionalCell<&'a VirtualWheelAlarm<'a, A>>,
+    /// Next alarm in the same list.
+    next: Cell<Option<&'a VirtualWheelAlarm<'a, A>>>,
+    client: OptionalCell<&'a dyn time::AlarmClient>,
+}
+
+impl<'a, A: Alarm<'a>> VirtualWheelAlarm<'a, A> {
+    pub fn new(mux_alarm: &'a MuxAlarmWheel<'a, A>) -> VirtualWheelAlarm<'a, A> {
+        let zero = A::Ticks::from(0);
+        VirtualWheelAlarm {
+            mux: mux_alarm,
+            reference: Cell::new(zero),
+            dt: Cell::new(zero),
+            expiration: Cell::new(0),
+            position: Cell::new(Position::Idle),
+            this: OptionalCell::empty(),
+            next: Cell::new(None),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Call this method immediately after new(), otherwise alarms won't fire.
+    pub fn setup(&'a self) {
+        self.this.set(self);
+    }
+}
+
+impl<'a, A: Alarm<'a>> Time for VirtualWheelAlarm<'a, A> {
+    type Frequency = A::Frequency;
+    type Ticks = A::Ticks;
+
+    fn now(&self) -> Self::Ticks {
+        self.mux.alarm.now()
+    }
+}
+
+impl<'a, A: Alarm<'a>> Alarm<'a> for VirtualWheelAlarm<'a, A> {
+    fn set_alarm_client(&self, client: &'a dyn time::AlarmClient) {
+        self.client.set(client);
+    }
+
+    fn disarm(&self) -> Result<(), ErrorCode> {
+        if self.position.get() != Position::Idle {
+            self.mux.remove(self);
+            self.mux.update_alarm();
+        }
+        Ok(())
+    }
+
+    fn is_armed(&self) -> bool {
+        self.position.get() != Position::Idle
+    }
+
+    fn set_alarm(&self, reference: Self::Ticks, dt: Self::Ticks) {
+        if self.position.get() != Position::Idle {
+            self.mux.remove(self);
+        }
+        self.reference.set(reference);
+        self.dt.set(dt);
+
+        // The reference is in the past, as for `MuxAlarm`. A reference in the
+        // future is taken to be almost a full counter range in the past, so
+        // that the alarm fires right away.
+        let now = self.mux.update_elapsed();
+        let since_reference = ticks_to_u64(now.wrapping_sub(reference));
+        let expiration = self
+            .mux
+            .elapsed
+            .get()
+            .saturating_sub(since_reference)
+            .saturating_add(ticks_to_u64(dt));
+        self.expiration.set(expiration);
+
+        self.this.map(|this| {
+            if self.mux.firing.get() {
+                this.position.set(Position::Deferred);
+                this.next.set(self.mux.deferred.take());
+                self.mux.deferred.set(Some(this));
+            } else {
+                self.mux.insert(this);
+                self.mux.update_alarm();
+            }
+        });
+    }
+
+    fn get_alarm(&self) -> Self::Ticks {
+        self.reference.get().wrapping_add(self.dt.get())
+    }
+
+    fn minimum_dt(&self) -> Self::Ticks {
+        self.mux.alarm.minimum_dt()
+    }
+}
+
+/// A set of virtual alarms multiplexed on top of a single alarm, sorted
+/// into a hierarchical timer wheel.
+pub struct MuxAlarmWheel<'a, A: Alarm<'a>> {
+    /// Underlying alarm, over which the virtual alarms are multiplexed.
+    alarm: &'a A,
+    /// Heads of the lists of alarms in each slot.
+    slots: [[Cell<Option<&'a VirtualWheelAlarm<'a, A>>>; SLOTS]; LEVELS],
+    /// Bitmask of the non-empty slots of each level.
+    occupied: [Cell<u16>; LEVELS],
+    /// Time up to which all slots have been processed.
+    current: Cell<u64>,
+    /// Counter value of the underlying alarm when `elapsed` was last updated.
+    last_now: Cell<A::Ticks>,
+    /// Time of `last_now` in the wheel's time base.
+    elapsed: Cell<u64>,
+    /// Alarms of the slot being processed.
+    processing: Cell<Option<&'a VirtualWheelAlarm<'a, A>>>,
+    /// Alarms set from alarm callbacks.
+    deferred: Cell<Option<&'a VirtualWheelAlarm<'a, A>>>,
+    /// Whether due alarms are being fired.
+    firing: Cell<bool>,
+    /// Time the underlying alarm is set to fire at, if it is armed.
+    next_expiration: Cell<Option<u64>>,
+}
+
+impl<'a, A: Alarm<'a>> MuxAlarmWheel<'a, A> {
+    pub fn new(alarm: &'a A) -> MuxAlarmWheel<'a, A> {
+        MuxAlarmWheel {
+            alarm,
+            slots: [const { [const { Cell::new(None) }; SLOTS] }; LEVELS],
+            occupied: [const { Cell::new(0) }; LEVELS],
+            current: Cell::new(0),
+            last_now: Cell::new(A::Ticks::from(0)),
+            elapsed: Cell::new(0),
+            processing: Cell::new(None),
+            deferred: Cell::new(None),
+            firing: Cell::new(false),
+            next_expiration: Cell::new(None),
+        }
+    }
+
+    /// Extends the counter of the underlying alarm into the wheel's time
+    /// base and returns its current value.
+    fn update_elapsed(&self) -> A::Ticks {
+        let now = self.alarm.now();
+        let delta = ticks_to_u64(now.wrapping_sub(self.last_now.get()));
+        self.last_now.set(now);
+        if self.elapsed.get() == 0 {
+            // Start far enough from zero that references in the past can
+            // always be represented.
+            self.elapsed.set(ticks_to_u64(A::Ticks::max_value()) + 1);
+            self.current.set(self.elapsed.get());
+        } else {
+            self.elapsed.set(self.elapsed.get() + delta);
+        }
+        now
+    }
+
+    /// Places `alarm` in the slot for its expiration time.
+    fn insert(&self, alarm: &'a VirtualWheelAlarm<'a, A>) {
+        if !self.firing.get() && self.occupied.iter().all(|occupied| occupied.get() == 0) {
+            // Nothing is pending, so the wheel can skip ahead.
+            self.current.set(self.elapsed.get());
+        }
+        let current = self.current.get();
+        let expiration = core::cmp::max(alarm.expiration.get(), current);
+        let level = level_for(current, expiration);
+        let slot = slot_for(level, expiration);
+
+        alarm.position.set(Position::Wheel(level, slot));
+        alarm.next.set(self.slots[level][slot].take());
+        self.slots[level][slot].set(Some(alarm));
+        self.occupied[level].set(self.occupied[level].get() | 1 << slot);
+    }
+
+    /// Unlinks `alarm` from the list it is in and disarms it.
+    fn remove(&self, alarm: &VirtualWheelAlarm<'a, A>) {
+        let head = match alarm.position.get() {
+            Position::Idle => return,
+            Position::Wheel(level, slot) => &self.slots[level][slot],
+            Position::Processing => &self.processing,
+            Position::Deferred => &self.deferred,
+        };
+
+        let mut link = head;
+        while let Some(cur) = link.get() {
+            if core::ptr::eq(cur, alarm) {
+                link.set(alarm.next.take());
+                break;
+            }
+            link = &cur.next;
+        }
+
+        if let Position::Wheel(level, slot) = alarm.position.get() {
+            if head.get().is_none() {
+                self.occupied[level].set(self.occupied[level].get() & !(1 << slot));
+            }
+        }
+        alarm.position.set(Position::Idle);
+    }
+
+    /// Returns the level and slot which come due next, and the time at which
+    /// they do.
+    fn next_slot(&self) -> Option<(usize, usize, u64)> {
+        let current = self.current.get();
+        // Alarms in lower levels always expire before those in higher levels.
+        let level = self
+            .occupied
+            .iter()
+            .position(|occupied| occupied.get() != 0)?;
+        let shift = level as u32 * SLOT_BITS;
+        let current_slot = slot_for(level, current);
+        // Due alarms are in the current slot of level 0. In the top level,
+        // the current slot holds alarms which are a full revolution ahead.
+        let first = if level == 0 {
+            current_slot
+        } else {
+            (current_slot + 1) % SLOTS
+        };
+        let offset = self.occupied[level]
+            .get()
+            .rotate_right(first as u32)
+            .trailing_zeros() as usize;
+        let slot = (first + offset) % SLOTS;
+
+        let level_range = 1 << (shift + SLOT_BITS);
+        let level_start = current & !(level_range - 1);
+        let mut deadline = level_start + ((slot as u64) << shift);
+        if level > 0 && deadline <= current {
+            deadline += level_range;
+        }
+        Some((level, slot, deadline))
+    }
+
+    /// Sets or disarms the underlying alarm for the next slot to come due.
+    fn update_alarm(&self) {
+        if self.firing.get() {
+            // Updated once all due alarms have fired.
+            return;
+        }
+        match self.next_slot() {
+            Some((_, _, deadline)) => {
+                if self.next_expiration.get() == Some(deadline) {
+                    return;
+                }
+                self.next_expiration.set(Some(deadline));
+                // Do not set the alarm more than half of the counter range
+                // ahead, so that `update_elapsed` never misses a wrap.
+                let dt = core::cmp::min(
+                    deadline.saturating_sub(self.elapsed.get()),
+                    ticks_to_u64(A::Ticks::half_max_value()),
+                );
+                self.alarm
+                    .set_alarm(self.last_now.get(), A::Ticks::from_or_max(dt));
+            }
+            None => {
+                self.next_expiration.set(None);
+                let _ = self.alarm.disarm();
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>> time::AlarmClient for MuxAlarmWheel<'a, A> {
+    /// Fires all alarms which have expired, and moves the alarms of higher
+    /// level slots which have come due to lower levels.
+    fn alarm(&self) {
+        self.update_elapsed();
+        let now = self.elapsed.get();
+        self.next_expiration.set(None);
+        self.firing.set(true);
+
+        while let Some((level, slot, deadline)) = self.next_slot() {
+            if deadline > now {
+                break;
+            }
+            self.current.set(deadline);
+            self.occupied[level].set(self.occupied[level].get() & !(1 << slot));
+            self.processing.set(self.slots[level][slot].take());
+            let mut alarms = self.processing.get();
+            while let Some(alarm) = alarms {
+                alarm.position.set(Position::Processing);
+                alarms = alarm.next.get();
+            }
+
+            // Alarm callbacks can disarm any alarm, including the ones still
+            // in `processing`, so take them out one at a time.
+            while let Some(alarm) = self.processing.get() {
+                self.processing.set(alarm.next.take());
+                alarm.position.set(Position::Idle);
+                if alarm.expiration.get() <= now {
+                    alarm.client.map(|client| client.alarm());
+                } else {
+                    self.insert(alarm);
+                }
+            }
+        }
+        self.current.set(now);
+        self.firing.set(false);
+
+        while let Some(alarm) = self.deferred.get() {
+            self.deferred.set(alarm.next.take());
+            self.insert(alarm);
+        }
+        self.update_alarm();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use crate::virtualizers::virtual_alarm::{MuxAlarm, VirtualMuxAlarm};
+    use core::cell::RefCell;
+    use std::vec::Vec;
+    use time::*;
+
+    /// Number of timers in the comparisons against `MuxAlarm`.
+    const TIMERS: usize = 64;
+
+    /// An alarm whose counter only advances when the test advances it, so
+    /// that both multiplexers see exactly the same times.
+    struct FakeAlarm<'a, T: Ticks> {
+        now: Cell<T>,
+        reference: Cell<T>,
+        dt: Cell<T>,
+        armed: Cell<bool>,
+        /// Number of calls to `now()`, as a measure of the work done.
+        now_calls: Cell<usize>,
+        client: OptionalCell<&'a dyn AlarmClient>,
+    }
+
+    impl<T: Ticks> FakeAlarm<'_, T> {
+        fn new(now: u32) -> Self {
+            Self {
+                now: Cell::new(now.into()),
+                reference: Cell::new(0.into()),
+                dt: Cell::new(0.into()),
+                armed: Cell::new(false),
+                now_calls: Cell::new(0),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        /// Returns the number of ticks until the alarm fires.
+        fn remaining(&self) -> u32 {
+            let now = self.now.get();
+            if now.within_range(self.reference.get(), self.get_alarm()) {
+                self.get_alarm().wrapping_sub(now).into_u32()
+            } else {
+                0
+            }
+        }
+
+        /// Advances time to the expiration of the alarm and calls the client.
+        fn fire(&self) {
+            self.now
+                .set(self.now.get().wrapping_add(self.remaining().into()));
+            self.armed.set(false);
+            self.client.map(|client| client.alarm());
+        }
+
+        /// Fires the alarm until it is no longer armed.
+        fn run_until_disarmed(&self) {
+            while self.armed.get() {
+                self.fire();
+            }
+        }
+
+        /// Advances time by `ticks`, firing the alarm whenever it expires.
+        fn run_for_ticks(&self, mut ticks: u32) {
+            while self.armed.get() && self.remaining() <= ticks {
+                ticks -= self.remaining();
+                self.fire();
+            }
+            self.now.set(self.now.get().wrapping_add(ticks.into()));
+        }
+    }
+
+    impl<T: Ticks> Time for FakeAlarm<'_, T> {
+        type Ticks = T;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> T {
+            self.now_calls.set(self.now_calls.get() + 1);
+            self.now.get()
+        }
+    }
+
+    impl<'a, T: Ticks> Alarm<'a> for FakeAlarm<'a, T> {
+        fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+            self.client.set(client);
+        }
+
+        fn set_alarm(&self, reference: T, dt: T) {
+            self.reference.set(reference);
+            self.dt.set(dt);
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> T {
+            self.reference.get().wrapping_add(self.dt.get())
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> T {
+            0.into()
+        }
+    }
+
+    /// A client which records when it fires, and sets its alarm again a
+    /// number of times with pseudo-random intervals, like a retransmission
+    /// timer.
+    struct Retransmit<'a, V: Alarm<'a>> {
+        id: usize,
+        alarm: &'a V,
+        seed: Cell<u32>,
+        max_dt: u32,
+        remaining: Cell<usize>,
+        fired: &'a RefCell<Vec<(u32, usize)>>,
+    }
+
+    impl<'a, V: Alarm<'a>> Retransmit<'a, V> {
+        fn new(
+            id: usize,
+            alarm: &'a V,
+            max_dt: u32,
+            rounds: usize,
+            fired: &'a RefCell<Vec<(u32, usize)>>,
+        ) -> Self {
+            Self {
+                id,
+                alarm,
+                seed: Cell::new(id as u32),
+                max_dt,
+                remaining: Cell::new(rounds),
+                fired,
+            }
+        }
+
+        fn start(&self) {
+            let seed = self.seed.get().wrapping_mul(1103515245).wrapping_add(12345);
+            self.seed.set(seed);
+            let dt = (seed >> 4) % self.max_dt;
+            self.alarm.set_alarm(self.alarm.now(), dt.into());
+        }
+    }
+
+    impl<'a, V: Alarm<'a>> AlarmClient for Retransmit<'a, V> {
+        fn alarm(&self) {
+            let now = self.alarm.now().into_u32();
+            self.fired.borrow_mut().push((now, self.id));
+            if self.remaining.get() > 1 {
+                self.remaining.set(self.remaining.get() - 1);
+                self.start();
+            }
+        }
+    }
+
+    /// Runs `TIMERS` retransmission timers on a `MuxAlarm` and returns when
+    /// each fired.
+    fn mux_alarm_fire_times<T: Ticks>(start: u32, max_dt: u32, rounds: usize) -> Vec<(u32, usize)> {
+        let alarm = FakeAlarm::<T>::new(start);
+        let mux = MuxAlarm::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        let v_alarms: [_; TIMERS] = core::array::from_fn(|_| VirtualMuxAlarm::new(&mux));
+        let fired = RefCell::new(Vec::new());
+        let clients: [_; TIMERS] =
+            core::array::from_fn(|id| Retransmit::new(id, &v_alarms[id], max_dt, rounds, &fired));
+        for (v_alarm, client) in v_alarms.iter().zip(&clients) {
+            v_alarm.setup();
+            v_alarm.set_alarm_client(client);
+            client.start();
+        }
+        alarm.run_until_disarmed();
+
+        let mut fired = fired.take();
+        fired.sort();
+        fired
+    }
+
+    /// Runs `TIMERS` retransmission timers on a `MuxAlarmWheel` and returns
+    /// when each fired.
+    fn wheel_fire_times<T: Ticks>(start: u32, max_dt: u32, rounds: usize) -> Vec<(u32, usize)> {
+        let alarm = FakeAlarm::<T>::new(start);
+        let mux = MuxAlarmWheel::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        let v_alarms: [_; TIMERS] = core::array::from_fn(|_| VirtualWheelAlarm::new(&mux));
+        let fired = RefCell::new(Vec::new());
+        let clients: [_; TIMERS] =
+            core::array::from_fn(|id| Retransmit::new(id, &v_alarms[id], max_dt, rounds, &fired));
+        for (v_alarm, client) in v_alarms.iter().zip(&clients) {
+            v_alarm.setup();
+            v_alarm.set_alarm_client(client);
+            client.start();
+        }
+        alarm.run_until_disarmed();
+        assert!(v_alarms.iter().all(|v_alarm| !v_alarm.is_armed()));
+
+        let mut fired = fired.take();
+        fired.sort();
+        fired
+    }
+
+    #[test]
+    fn test_level_for() {
+        assert_eq!(level_for(0x100, 0x100), 0);
+        assert_eq!(level_for(0x100, 0x10F), 0);
+        assert_eq!(level_for(0x10F, 0x110), 1);
+        assert_eq!(level_for(0x100, 0x1FF), 1);
+        assert_eq!(level_for(0x100, 0x200), 2);
+        assert_eq!(level_for(0, SPAN - 1), LEVELS - 1);
+        assert_eq!(level_for(0, SPAN << 8), LEVELS - 1);
+    }
+
+    #[test]
+    fn test_ticks_to_u64() {
+        assert_eq!(ticks_to_u64(Ticks32::from(u32::MAX)), u32::MAX as u64);
+        assert_eq!(ticks_to_u64(Ticks24::from(0xFF_FFFF)), 0xFF_FFFF);
+        assert_eq!(ticks_to_u64(Ticks64::from(1u64 << 40)), 1 << 40);
+        assert_eq!(ticks_to_u64(Ticks64::from((1u64 << 63) - 1)), (1 << 63) - 1);
+    }
+
+    #[test]
+    fn test_short_timers_match_mux_alarm() {
+        let expected = mux_alarm_fire_times::<Ticks32>(1_000, 5_000, 20);
+        assert_eq!(expected.len(), TIMERS * 20);
+        assert_eq!(wheel_fire_times::<Ticks32>(1_000, 5_000, 20), expected);
+    }
+
+    #[test]
+    fn test_long_timers_match_mux_alarm() {
+        // Intervals beyond the span of the wheel are sorted again from the
+        // top level.
+        let expected = mux_alarm_fire_times::<Ticks32>(1_000, 1 << 28, 5);
+        assert_eq!(expected.len(), TIMERS * 5);
+        assert_eq!(wheel_fire_times::<Ticks32>(1_000, 1 << 28, 5), expected);
+    }
+
+    #[test]
+    fn test_wrapping_counter_matches_mux_alarm() {
+        // A 24 bit counter wraps several times during the test.
+        let expected = mux_alarm_fire_times::<Ticks24>(0xFF_F000, 1 << 22, 10);
+        assert_eq!(expected.len(), TIMERS * 10);
+        assert_eq!(
+            wheel_fire_times::<Ticks24>(0xFF_F000, 1 << 22, 10),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_fewer_now_calls_than_mux_alarm() {
+        // One timer fires while all the others are set far into the future.
+        // `MuxAlarm` reads the counter for each of them, the wheel only
+        // looks at the slot which came due.
+
+        let mux_alarm_calls = {
+            let alarm = FakeAlarm::<Ticks32>::new(0);
+            let mux = MuxAlarm::new(&alarm);
+            alarm.set_alarm_client(&mux);
+            let v_alarms: [_; TIMERS] = core::array::from_fn(|_| VirtualMuxAlarm::new(&mux));
+            let fired = RefCell::new(Vec::new());
+            let client = Retransmit::new(0, &v_alarms[0], 1, 0, &fired);
+            for v_alarm in &v_alarms {
+                v_alarm.setup();
+                v_alarm.set_alarm(v_alarm.now(), 1_000_000.into());
+            }
+            v_alarms[0].set_alarm_client(&client);
+            v_alarms[0].set_alarm(v_alarms[0].now(), 1_000.into());
+            alarm.now_calls.set(0);
+            alarm.run_for_ticks(1_500);
+            assert_eq!(fired.borrow().len(), 1);
+            alarm.now_calls.get()
+        };
+
+        let wheel_calls = {
+            let alarm = FakeAlarm::<Ticks32>::new(0);
+            let mux = MuxAlarmWheel::new(&alarm);
+            alarm.set_alarm_client(&mux);
+            let v_alarms: [_; TIMERS] = core::array::from_fn(|_| VirtualWheelAlarm::new(&mux));
+            let fired = RefCell::new(Vec::new());
+            let client = Retransmit::new(0, &v_alarms[0], 1, 0, &fired);
+            for v_alarm in &v_alarms {
+                v_alarm.setup();
+                v_alarm.set_alarm(v_alarm.now(), 1_000_000.into());
+            }
+            v_alarms[0].set_alarm_client(&client);
+            v_alarms[0].set_alarm(v_alarms[0].now(), 1_000.into());
+            alarm.now_calls.set(0);
+            alarm.run_for_ticks(1_500);
+            assert_eq!(fired.borrow().len(), 1);
+            alarm.now_calls.get()
+        };
+
+        assert!(mux_alarm_calls > TIMERS);
+        assert!(wheel_calls < 8);
+    }
+
+    struct ClientCounter(Cell<usize>);
+
+    impl AlarmClient for ClientCounter {
+        fn alarm(&self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_single_max_ticks_dt() {
+        let alarm = FakeAlarm::<Ticks32>::new(1_000);
+        let mux = MuxAlarmWheel::new(&alarm);
+        alarm.set_alarm_client(&mux);
+        let client = ClientCounter(Cell::new(0));
+
+        let v_alarm = VirtualWheelAlarm::new(&mux);
+        v_alarm.setup();
+        v_alarm.set_alarm_client(&client);
+        v_alarm.set_alarm(v_alarm.now(), u32::MAX.into());
+
+        alarm.run_for_ticks(u32::MAX - 1);
+        assert_eq!(client.0.get(), 0);
+        alarm.run_for_ticks(1);
+        assert_eq!(client.0.get(), 1);
+        assert!(!v_alarm.is_armed());
+        assert!(!alarm.is_armed());
+    }
+
+    #[test]
+    fn test_reference_in_the_past() {
+        let alarm = FakeAlarm::<Ticks32>::new(1_000);
+        let mux = MuxAlarmWheel::new(&alarm);
+        alarm.set_alarm_client(&mux);
+        let client = ClientCounter(Cell::new(0));
+
+        let v_alarms = [VirtualWheelAlarm::new(&mux), VirtualWheelAlarm::new(&mux)];
+        for v_alarm in &v_alarms {
+            v_alarm.setup();
+            v_alarm.set_alarm_client(&client);
+        }
+        // Already expired, and expiring 100 ticks from now.
+        v_alarms[0].set_alarm(500.into(), 200.into());
+        v_alarms[1].set_alarm(500.into(), 600.into());
+
+        alarm.run_for_ticks(0);
+        assert_eq!(client.0.get(), 1);
+        alarm.run_for_ticks(99);
+        assert_eq!(client.0.get(), 1);
+        alarm.run_for_ticks(1);
+        assert_eq!(client.0.get(), 2);
+    }
+
+    #[test]
+    fn test_disarm() {
+        let alarm = FakeAlarm::<Ticks32>::new(1_000);
+        let mux = MuxAlarmWheel::new(&alarm);
+        alarm.set_alarm_client(&mux);
+        let client = ClientCounter(Cell::new(0));
+
+        let v_alarms = [
+            VirtualWheelAlarm::new(&mux),
+            VirtualWheelAlarm::new(&mux),
+            VirtualWheelAlarm::new(&mux),
+        ];
+        for v_alarm in &v_alarms {
+            v_alarm.setup();
+            v_alarm.set_alarm_client(&client);
+            v_alarm.set_alarm(v_alarm.now(), 100.into());
+        }
+        assert_eq!(v_alarms[1].disarm(), Ok(()));
+        assert!(!v_alarms[1].is_armed());
+
+        alarm.run_until_disarmed();
+        assert_eq!(client.0.get(), 2);
+
+        v_alarms[2].set_alarm(v_alarms[2].now(), 100.into());
+        assert_eq!(v_alarms[2].disarm(), Ok(()));
+        assert!(!alarm.is_armed());
+    }
+
+    #[test]
+    fn test_set_earlier_alarm() {
+        let alarm = FakeAlarm::<Ticks32>::new(1_000);
+        let mux = MuxAlarmWheel::new(&alarm);
+        alarm.set_alarm_client(&mux);
+        let client = ClientCounter(Cell::new(0));
+
+        let v_alarm = VirtualWheelAlarm::new(&mux);
+        v_alarm.setup();
+        v_alarm.set_alarm_client(&client);
+        v_alarm.set_alarm(v_alarm.now(), 10_000.into());
+        v_alarm.set_alarm(v_alarm.now(), 10.into());
+        assert_eq!(v_alarm.get_alarm(), 1_010.into());
+
+        alarm.run_for_ticks(10);
+        assert_eq!(client.0.get(), 1);
+        alarm.run_for_ticks(20_000);
+        assert_eq!(client.0.get(), 1);
+    }
+
+    struct SetAlarmClient<'a> {
+        alarm: &'a VirtualWheelAlarm<'a, FakeAlarm<'a, Ticks32>>,
+        dt: u32,
+    }
+
+    impl AlarmClient for SetAlarmClient<'_> {
+        fn alarm(&self) {
+            self.alarm.set_alarm(self.alarm.now(), self.dt.into());
+        }
+    }
+
+    #[test]
+    fn test_alarm_set_during_firing_fires_in_next_interrupt() {
+        let alarm = FakeAlarm::<Ticks32>::new(1_000);
+        let mux = MuxAlarmWheel::new(&alarm);
+        alarm.set_alarm_client(&mux);
+
+        let v_alarms = [VirtualWheelAlarm::new(&mux), VirtualWheelAlarm::new(&mux)];
+        for v_alarm in &v_alarms {
+            v_alarm.setup();
+        }
+        let set_v1_alarm = SetAlarmClient {
+            alarm: &v_alarms[1],
+            dt: 0,
+        };
+        v_alarms[0].set_alarm_client(&set_v1_alarm);
+        let counter = ClientCounter(Cell::new(0));
+        v_alarms[1].set_alarm_client(&counter);
+
+        v_alarms[0].set_alarm(v_alarms[0].now(), 10.into());
+        alarm.fire();
+        assert_eq!(counter.0.get(), 0);
+        assert!(alarm.is_armed());
+
+        alarm.fire();
+        assert_eq!(counter.0.get(), 1);
+        assert_eq!(alarm.now.get(), 1_010.into());
+        assert!(!alarm.is_armed());
+    }
+}
@@ -7,16 +7,20 @@
 //!
 //! <https://cdn.sparkfun.com/assets/learn_tutorials/4/1/9/BST-BME280_DS001-10.pdf>
 //!
+//! The pressure compensation depends on the temperature, so a pressure
+//! reading also reads the temperature and updates the stored `t_fine` value.
 
 use core::cell::Cell;
 use kernel::hil::i2c::{self, I2CClient, I2CDevice};
-use kernel::hil::sensors::{HumidityClient, HumidityDriver, TemperatureClient, TemperatureDriver};
+use kernel::hil::sensors::{
+    HumidityClient, HumidityDriver, PressureClient, PressureDriver, TemperatureClient,
+    TemperatureDriver,
+};
 use kernel::utilities::cells::{OptionalCell, TakeCell};
 use kernel::ErrorCode;
 
 const HUM_MSB: u8 = 0xFD;
 const TEMP_MSB: u8 = 0xFA;
-#[allow(dead_code)]
 const PRESS_MSB: u8 = 0xF7;
 #[allow(dead_code)]
 const CONFIG: u8 = 0xF5;
@@ -44,7 +48,6 @@ enum DeviceState {
     Normal,
 }
 
-#[allow(dead_code)]
 #[derive(Clone, Copy, PartialEq)]
 enum Operation {
     None,
@@ -69,12 +72,12 @@ struct CalibrationData {
     press8: u16,
     press9: u16,
 
-    hum1: u16,
-    hum2: u16,
-    hum3: u16,
-    hum4: u16,
-    hum5: u16,
-    hum6: u16,
+    hum1: u8,
+    hum2: i16,
+    hum3: u8,
+    hum4: i16,
+    hum5: i16,
+    hum6: i8,
 }
 
 pub struct Bme280<'a, I: I2CDevice> {
@@ -83,9 +86,10 @@ pub struct Bme280<'a, I: I2CDevice> {
     calibration: Cell<CalibrationData>,
     temperature_client: OptionalCell<&'a dyn TemperatureClient>,
     humidity_client: OptionalCell<&'a dyn HumidityClient>,
+    pressure_client: OptionalCell<&'a dyn PressureClient>,
     state: Cell<DeviceState>,
     op: Cell<Operation>,
-    t_fine: Cell<usize>,
+    t_fine: Cell<i32>,
 }
 
 impl<'a, I: I2CDevice> Bme280<'a, I> {
@@ -96,6 +100,7 @@ impl<'a, I: I2CDevice> Bme280<'a, I> {
             calibration: Cell::new(CalibrationData::default()),
             temperature_client: OptionalCell::empty(),
             humidity_client: OptionalCell::empty(),
+            pressure_client: OptionalCell::empty(),
             state: Cell::new(DeviceState::Identify),
             op: Cell::new(Operation::None),
             t_fine: Cell::new(0),
@@ -111,6 +116,82 @@ impl<'a, I: I2CDevice> Bme280<'a, I> {
             }
         });
     }
+
+    /// Computes the fine resolution temperature value used by the pressure
+    /// and humidity compensation, as given in the datasheet.
+    fn compensate_t_fine(&self, adc_temperature: i32) -> i32 {
+        let calib = self.calibration.get();
+        let temp1 = calib.temp1 as i32;
+        let temp2 = calib.temp2 as i16 as i32;
+        let temp3 = calib.temp3 as i16 as i32;
+
+        let var1 = (((adc_temperature >> 3) - (temp1 << 1)) * temp2) >> 11;
+        let var2 = (((((adc_temperature >> 4) - temp1) * ((adc_temperature >> 4) - temp1)) >> 12)
+            * temp3)
+            >> 14;
+        var1 + var2
+    }
+
+    /// Returns the pressure in Pa, using the 32 bit integer compensation
+    /// formula from the datasheet.
+    fn compensate_pressure(&self, adc_pressure: i32) -> Option<u32> {
+        let calib = self.calibration.get();
+        let press1 = calib.press1 as i32;
+        let press2 = calib.press2 as i16 as i32;
+        let press3 = calib.press3 as i16 as i32;
+        let press4 = calib.press4 as i16 as i32;
+        let press5 = calib.press5 as i16 as i32;
+        let press6 = calib.press6 as i16 as i32;
+        let press7 = calib.press7 as i16 as i32;
+        let press8 = calib.press8 as i16 as i32;
+        let press9 = calib.press9 as i16 as i32;
+
+        let var1 = (self.t_fine.get() >> 1) - 64000;
+        let var2 = (((var1 >> 2) * (var1 >> 2)) >> 11) * press6;
+        let var2 = var2 + ((var1 * press5) << 1);
+        let var2 = (var2 >> 2) + (press4 << 16);
+        let var1 =
+            (((press3 * (((var1 >> 2) * (var1 >> 2)) >> 13)) >> 3) + ((press2 * var1) >> 1)) >> 18;
+        let var1 = ((32768 + var1) * press1) >> 15;
+        if var1 == 0 {
+            // Avoid a division by zero
+            return None;
+        }
+
+        let p = ((1048576 - adc_pressure) - (var2 >> 12)) as u32 * 3125;
+        let p = if p < 0x80000000 {
+            (p << 1) / var1 as u32
+        } else {
+            (p / var1 as u32) * 2
+        };
+        let var1 = (press9 * ((((p >> 3) * (p >> 3)) >> 13) as i32)) >> 12;
+        let var2 = (((p >> 2) as i32) * press8) >> 13;
+        Some((p as i32 + ((var1 + var2 + press7) >> 4)) as u32)
+    }
+
+    /// Returns the relative humidity in hundredths of a percent, using the 32
+    /// bit integer compensation formula from the datasheet.
+    fn compensate_humidity(&self, adc_humidity: i32) -> usize {
+        let calib = self.calibration.get();
+        let hum1 = calib.hum1 as i32;
+        let hum2 = calib.hum2 as i32;
+        let hum3 = calib.hum3 as i32;
+        let hum4 = calib.hum4 as i32;
+        let hum5 = calib.hum5 as i32;
+        let hum6 = calib.hum6 as i32;
+
+        let var1 = self.t_fine.get() - 76800;
+        let var1 = ((((adc_humidity << 14) - (hum4 << 20) - (hum5 * var1)) + 16384) >> 15)
+            * (((((((var1 * hum6) >> 10) * (((var1 * hum3) >> 11) + 32768)) >> 10) + 2097152)
+                * hum2
+                + 8192)
+                >> 14);
+        let var1 = var1 - (((((var1 >> 15) * (var1 >> 15)) >> 7) * hum1) >> 4);
+        let var1 = var1.clamp(0, 419430400);
+
+        // var1 is the humidity in %RH as an unsigned 22.10 fixed point value
+        ((var1 >> 12) * 100 / 1024) as usize
+    }
 }
 
 impl<'a, I: I2CDevice> TemperatureDriver<'a> for Bme280<'a, I> {
@@ -163,6 +244,33 @@ impl<'a, I: I2CDevice> HumidityDriver<'a> for Bme280<'a, I> {
     }
 }
 
+impl<'a, I: I2CDevice> PressureDriver<'a> for Bme280<'a, I> {
+    fn set_client(&self, client: &'a dyn PressureClient) {
+        self.pressure_client.set(client);
+    }
+
+    fn read_atmospheric_pressure(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != DeviceState::Normal {
+            return Err(ErrorCode::BUSY);
+        }
+
+        if self.op.get() != Operation::None {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.buffer.take().map(|buffer| {
+            // Read both the pressure and the temperature, which is needed to
+            // compensate the pressure reading
+            buffer[0] = PRESS_MSB;
+
+            self.op.set(Operation::Pressure);
+            self.i2c.write_read(buffer, 1, 6).unwrap();
+        });
+
+        Ok(())
+    }
+}
+
 impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
     fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
         if let Err(i2c_err) = status {
@@ -174,7 +282,8 @@ impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
                         .map(|client| client.callback(Err(i2c_err.into())));
                 }
                 Operation::Pressure => {
-                    unimplemented!();
+                    self.pressure_client
+                        .map(|client| client.callback(Err(i2c_err.into())));
                 }
                 Operation::Humidity => {
                     self.humidity_client.map(|client| client.callback(0));
@@ -212,7 +321,7 @@ impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
                 calib.press7 = buffer[18] as u16 | (buffer[19] as u16) << 8;
                 calib.press8 = buffer[20] as u16 | (buffer[21] as u16) << 8;
                 calib.press9 = buffer[22] as u16 | (buffer[23] as u16) << 8;
-                calib.hum1 = buffer[25] as u16;
+                calib.hum1 = buffer[25];
                 self.calibration.set(calib);
 
                 if calib.temp1 == 0 || calib.temp2 == 0 || calib.temp3 == 0 {
@@ -231,11 +340,13 @@ impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
             }
             DeviceState::CalibrationHigh => {
                 let mut calib = self.calibration.take();
-                calib.hum2 = buffer[0] as u16 | (buffer[1] as u16) << 8;
-                calib.hum3 = buffer[3] as u16;
-                calib.hum4 = buffer[4] as u16 | (buffer[5] as u16) << 4;
-                calib.hum5 = (buffer[6] as u16 >> 4) | (buffer[7] as u16) << 4;
-                calib.hum6 = buffer[8] as u16;
+                calib.hum2 = (buffer[0] as u16 | (buffer[1] as u16) << 8) as i16;
+                calib.hum3 = buffer[2];
+                // H4 and H5 are signed 12 bit values sharing the nibbles of
+                // register 0xE5
+                calib.hum4 = (buffer[3] as i8 as i16) << 4 | (buffer[4] & 0x0F) as i16;
+                calib.hum5 = (buffer[5] as i8 as i16) << 4 | (buffer[4] >> 4) as i16;
+                calib.hum6 = buffer[6] as i8;
                 self.calibration.set(calib);
 
                 buffer[0] = CTRL_MEAS;
@@ -269,10 +380,9 @@ impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
                 match self.op.get() {
                     Operation::None => (),
                     Operation::Temp => {
-                        let calib = self.calibration.get();
-                        let adc_temperature = (buffer[0] as usize) << 12
-                            | (buffer[1] as usize) << 4
-                            | (((buffer[2] as usize) >> 4) & 0x0F);
+                        let adc_temperature = (buffer[0] as i32) << 12
+                            | (buffer[1] as i32) << 4
+                            | (((buffer[2] as i32) >> 4) & 0x0F);
 
                         if adc_temperature == 0 {
                             // We got a misread, try again
@@ -282,30 +392,44 @@ impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
                             return;
                         }
 
-                        let var1 = (((adc_temperature >> 3) - ((calib.temp1 as usize) << 1))
-                            * (calib.temp2 as usize))
-                            >> 11;
-                        let var2 = (((((adc_temperature >> 4) - (calib.temp1 as usize))
-                            * ((adc_temperature >> 4) - (calib.temp1 as usize)))
-                            >> 12)
-                            * (calib.temp3 as usize))
-                            >> 14;
+                        self.t_fine.set(self.compensate_t_fine(adc_temperature));
 
-                        self.t_fine.set(var1 + var2);
-
-                        let temperature = ((self.t_fine.get() * 5 + 128) >> 8) / 100;
+                        // The HIL reports the temperature in centi-Celsius
+                        let temperature = (self.t_fine.get() * 5 + 128) >> 8;
 
                         self.temperature_client
-                            .map(|client| client.callback(Ok(temperature as i32)));
+                            .map(|client| client.callback(Ok(temperature)));
                     }
                     Operation::Pressure => {
-                        unimplemented!();
+                        let adc_pressure = (buffer[0] as i32) << 12
+                            | (buffer[1] as i32) << 4
+                            | (((buffer[2] as i32) >> 4) & 0x0F);
+                        let adc_temperature = (buffer[3] as i32) << 12
+                            | (buffer[4] as i32) << 4
+                            | (((buffer[5] as i32) >> 4) & 0x0F);
+
+                        if adc_pressure == 0 || adc_temperature == 0 {
+                            // We got a misread, try again
+                            self.buffer.replace(buffer);
+                            self.op.set(Operation::None);
+                            let _ = self.read_atmospheric_pressure();
+                            return;
+                        }
+
+                        self.t_fine.set(self.compensate_t_fine(adc_temperature));
+
+                        // The HIL reports the pressure in hPa
+                        let pressure = self
+                            .compensate_pressure(adc_pressure)
+                            .map(|pressure| pressure / 100)
+                            .ok_or(ErrorCode::FAIL);
+
+                        self.pressure_client.map(|client| client.callback(pressure));
                     }
                     Operation::Humidity => {
-                        let calib = self.calibration.get();
-                        let adc_hum = (buffer[0] as usize) << 8 | buffer[1] as usize;
+                        let adc_humidity = (buffer[0] as i32) << 8 | buffer[1] as i32;
 
-                        if adc_hum == 0 {
+                        if adc_humidity == 0 {
                             // We got a misread, try again
                             self.buffer.replace(buffer);
                             self.op.set(Operation::None);
@@ -313,27 +437,7 @@ impl<'a, I: I2CDevice> I2CClient for Bme280<'a, I> {
                             return;
                         }
 
-                        let t_fine_offset = self.t_fine.get() - 76800;
-
-                        // This is straight from the datasheet
-                        let var1 = ((((adc_hum << 14)
-                            - ((calib.hum4 as usize) << 20)
-                            - ((calib.hum5 as usize) * t_fine_offset))
-                            + 16384)
-                            >> 15)
-                            * (((((((t_fine_offset * (calib.hum6 as usize)) >> 10)
-                                * (((t_fine_offset * (calib.hum3 as usize)) >> 11) + 32768))
-                                >> 10)
-                                + 2097152)
-                                * (calib.hum2 as usize)
-                                + 8192)
-                                >> 14);
-                        let var2 = var1
-                            - (((((var1 >> 15) * (var1 >> 15)) >> 7) * (calib.hum1 as usize)) >> 4);
-
-                        let var6 = if var2 > 419430400 { 419430400 } else { var2 };
-
-                        let hum = (var6 >> 12) / 1024;
+                        let hum = self.compensate_humidity(adc_humidity);
 
                         self.humidity_client.map(|client| client.callback(hum));
                     }
@@ -0,0 +1,308 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Compresses the entries of a log.
+//!
+//! `CompressedLog` sits between a log client and a log storage capsule (such
+//! as [`Log`](crate::log::Log)) and implements the `LogRead` and `LogWrite`
+//! HILs itself. Every entry is compressed on its own with the configured
+//! [`Compressor`], so entries can still be read individually after older
+//! ones are overwritten by a circular log. Entries that do not get smaller
+//! are stored uncompressed; one header byte records which is the case.
+//!
+//! All operations go through one scratch buffer, which must be at least one
+//! byte longer than the largest entry.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let compressed_log = static_init!(
+//!     capsules_extra::compressed_log::CompressedLog<
+//!         'static,
+//!         capsules_extra::log::Log<'static, FlashCtrl>,
+//!         capsules_extra::compression::lzss::LzssEncoder,
+//!         capsules_extra::compression::lzss::LzssDecoder,
+//!     >,
+//!     capsules_extra::compressed_log::CompressedLog::new(
+//!         log,
+//!         capsules_extra::compression::lzss::LzssEncoder::new(),
+//!         capsules_extra::compression::lzss::LzssDecoder::new(),
+//!         scratch_buffer,
+//!     )
+//! );
+//! log.set_read_client(compressed_log);
+//! log.set_append_client(compressed_log);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::hil::log::{LogRead, LogReadClient, LogWrite, LogWriteClient};
+use kernel::utilities::cells::{MapCell, OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+use crate::compression::{Compressor, Decompressor};
+
+/// Header byte of entries stored as is.
+const ENTRY_RAW: u8 = 0;
+/// Header byte of compressed entries.
+const ENTRY_COMPRESSED: u8 = 1;
+
+pub struct CompressedLog<'a, L: LogRead<'a> + LogWrite<'a>, C: Compressor, D: Decompressor> {
+    log: &'a L,
+    compressor: MapCell<C>,
+    decompressor: MapCell<D>,
+    scratch: TakeCell<'static, [u8]>,
+    client_buffer: TakeCell<'static, [u8]>,
+    client_length: Cell<usize>,
+    read_client: OptionalCell<&'a dyn LogReadClient>,
+    append_client: OptionalCell<&'a dyn LogWriteClient>,
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>, C: Compressor, D: Decompressor> CompressedLog<'a, L, C, D> {
+    pub fn new(
+        log: &'a L,
+        compressor: C,
+        decompressor: D,
+        scratch: &'static mut [u8],
+    ) -> CompressedLog<'a, L, C, D> {
+        CompressedLog {
+            log,
+            compressor: MapCell::new(compressor),
+            decompressor: MapCell::new(decompressor),
+            scratch: TakeCell::new(scratch),
+            client_buffer: TakeCell::empty(),
+            client_length: Cell::new(0),
+            read_client: OptionalCell::empty(),
+            append_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Encodes `data` as an entry in `entry` and returns the entry length.
+    fn encode_entry(&self, data: &[u8], entry: &mut [u8]) -> Result<usize, ErrorCode> {
+        if entry.is_empty() {
+            return Err(ErrorCode::SIZE);
+        }
+        let compressed_len = self.compressor.map_or(None, |compressor| {
+            compressor.reset();
+            let (consumed, produced) = compressor.compress(data, &mut entry[1..]);
+            if consumed < data.len() {
+                return None;
+            }
+            let (flushed, done) = compressor.finish(&mut entry[1 + produced..]);
+            if done {
+                Some(produced + flushed)
+            } else {
+                None
+            }
+        });
+
+        match compressed_len {
+            Some(len) if len < data.len() => {
+                entry[0] = ENTRY_COMPRESSED;
+                Ok(1 + len)
+            }
+            _ => {
+                if entry.len() < 1 + data.len() {
+                    return Err(ErrorCode::SIZE);
+                }
+                entry[0] = ENTRY_RAW;
+                entry[1..1 + data.len()].copy_from_slice(data);
+                Ok(1 + data.len())
+            }
+        }
+    }
+
+    /// Decodes `entry` into `data` and returns the data length.
+    fn decode_entry(&self, entry: &[u8], data: &mut [u8]) -> Result<usize, ErrorCode> {
+        match entry.split_first() {
+            Some((&ENTRY_RAW, payload)) => {
+                if data.len() < payload.len() {
+                    return Err(ErrorCode::SIZE);
+                }
+                data[..payload.len()].copy_from_slice(payload);
+                Ok(payload.len())
+            }
+            Some((&ENTRY_COMPRESSED, payload)) => {
+                self.decompressor
+                    .map_or(Err(ErrorCode::FAIL), |decompressor| {
+                        decompressor.reset();
+                        let (consumed, produced) = decompressor.decompress(payload, data);
+                        if consumed < payload.len() || !decompressor.is_finished() {
+                            Err(ErrorCode::SIZE)
+                        } else {
+                            Ok(produced)
+                        }
+                    })
+            }
+            _ => Err(ErrorCode::FAIL),
+        }
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>, C: Compressor, D: Decompressor> LogRead<'a>
+    for CompressedLog<'a, L, C, D>
+{
+    type EntryID = L::EntryID;
+
+    fn set_read_client(&'a self, read_client: &'a dyn LogReadClient) {
+        self.read_client.set(read_client);
+    }
+
+    /// Reads and decompresses the next entry. Fails with `SIZE` in the
+    /// `read_done` callback if the decompressed entry does not fit in
+    /// `length` bytes.
+    fn read(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if buffer.len() < length {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        let scratch = match self.scratch.take() {
+            Some(scratch) => scratch,
+            None => return Err((ErrorCode::BUSY, buffer)),
+        };
+        let scratch_len = scratch.len();
+        match self.log.read(scratch, scratch_len) {
+            Ok(()) => {
+                self.client_buffer.replace(buffer);
+                self.client_length.set(length);
+                Ok(())
+            }
+            Err((e, scratch)) => {
+                self.scratch.replace(scratch);
+                Err((e, buffer))
+            }
+        }
+    }
+
+    fn log_start(&self) -> Self::EntryID {
+        self.log.log_start()
+    }
+
+    fn log_end(&self) -> Self::EntryID {
+        self.log.log_end()
+    }
+
+    fn next_read_entry_id(&self) -> Self::EntryID {
+        self.log.next_read_entry_id()
+    }
+
+    fn seek(&self, entry: Self::EntryID) -> Result<(), ErrorCode> {
+        self.log.seek(entry)
+    }
+
+    /// Returns the capacity of the underlying log, which is the amount of
+    /// compressed data it can hold.
+    fn get_size(&self) -> usize {
+        self.log.get_size()
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>, C: Compressor, D: Decompressor> LogWrite<'a>
+    for CompressedLog<'a, L, C, D>
+{
+    fn set_append_client(&'a self, append_client: &'a dyn LogWriteClient) {
+        self.append_client.set(append_client);
+    }
+
+    /// Compresses and appends an entry. Fails with `SIZE` if the entry does
+    /// not fit in the scratch buffer.
+    fn append(
+        &self,
+        buffer: &'static mut [u8],
+        length: usize,
+    ) -> Result<(), (ErrorCode, &'static mut [u8])> {
+        if buffer.len() < length {
+            return Err((ErrorCode::INVAL, buffer));
+        }
+        let scratch = match self.scratch.take() {
+            Some(scratch) => scratch,
+            None => return Err((ErrorCode::BUSY, buffer)),
+        };
+        let entry_len = match self.encode_entry(&buffer[..length], scratch) {
+            Ok(entry_len) => entry_len,
+            Err(e) => {
+                self.scratch.replace(scratch);
+                return Err((e, buffer));
+            }
+        };
+        match self.log.append(scratch, entry_len) {
+            Ok(()) => {
+                self.client_buffer.replace(buffer);
+                self.client_length.set(length);
+                Ok(())
+            }
+            Err((e, scratch)) => {
+                self.scratch.replace(scratch);
+                Err((e, buffer))
+            }
+        }
+    }
+
+    fn sync(&self) -> Result<(), ErrorCode> {
+        self.log.sync()
+    }
+
+    fn erase(&self) -> Result<(), ErrorCode> {
+        self.log.erase()
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>, C: Compressor, D: Decompressor> LogReadClient
+    for CompressedLog<'a, L, C, D>
+{
+    fn read_done(&self, scratch: &'static mut [u8], length: usize, error: Result<(), ErrorCode>) {
+        let mut buffer = self.client_buffer.take();
+        let result = error.and_then(|()| {
+            buffer
+                .as_deref_mut()
+                .map_or(Err(ErrorCode::FAIL), |buffer| {
+                    let client_length = self.client_length.get();
+                    self.decode_entry(&scratch[..length], &mut buffer[..client_length])
+                })
+        });
+        self.scratch.replace(scratch);
+
+        if let Some(buffer) = buffer {
+            self.read_client.map(move |client| match result {
+                Ok(length) => client.read_done(buffer, length, Ok(())),
+                Err(e) => client.read_done(buffer, 0, Err(e)),
+            });
+        }
+    }
+
+    fn seek_done(&self, error: Result<(), ErrorCode>) {
+        self.read_client.map(|client| client.seek_done(error));
+    }
+}
+
+impl<'a, L: LogRead<'a> + LogWrite<'a>, C: Compressor, D: Decompressor> LogWriteClient
+    for CompressedLog<'a, L, C, D>
+{
+    fn append_done(
+        &self,
+        scratch: &'static mut [u8],
+        _length: usize,
+        records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        self.scratch.replace(scratch);
+        if let Some(buffer) = self.client_buffer.take() {
+            let length = self.client_length.get();
+            self.append_client
+                .map(move |client| client.append_done(buffer, length, records_lost, error));
+        }
+    }
+
+    fn sync_done(&self, error: Result<(), ErrorCode>) {
+        self.append_client.map(|client| client.sync_done(error));
+    }
+
+    fn erase_done(&self, error: Result<(), ErrorCode>) {
+        self.append_client.map(|client| client.erase_done(error));
+    }
+}
@@ -0,0 +1,382 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! LZSS codec in the style of heatshrink.
+//!
+//! The compressed stream is a sequence of bit-packed tokens, most significant
+//! bit first:
+//!
+//! - `1` followed by 8 bits: a literal byte.
+//! - `0` followed by [`WINDOW_BITS`] bits of distance - 1 and [`LENGTH_BITS`]
+//!   bits of length - 2: a copy of 2 to 17 bytes from up to 256 bytes back.
+//!
+//! The last byte is padded with zero bits. Padding is shorter than any
+//! token, so it is never decoded as data.
+//!
+//! The encoder keeps 512 bytes of state and the decoder 256 bytes. The
+//! encoder searches the whole window for every token, which is slow for
+//! large inputs but fine for log entries and radio payloads of a few hundred
+//! bytes.
+
+use super::{Compressor, Decompressor};
+
+/// Number of bits used for copy distances.
+pub const WINDOW_BITS: u32 = 8;
+/// Number of bits used for copy lengths.
+pub const LENGTH_BITS: u32 = 4;
+
+const WINDOW_LEN: usize = 1 << WINDOW_BITS;
+const MIN_MATCH: usize = 2;
+const MAX_MATCH: usize = MIN_MATCH + (1 << LENGTH_BITS) - 1;
+const COPY_TOKEN_BITS: u32 = 1 + WINDOW_BITS + LENGTH_BITS;
+const LITERAL_TOKEN_BITS: u32 = 1 + 8;
+
+pub struct LzssEncoder {
+    // The window of already encoded bytes, followed by bytes still to be
+    // encoded.
+    buffer: [u8; 2 * WINDOW_LEN],
+    // Next byte to encode.
+    pos: usize,
+    // End of the buffered input.
+    end: usize,
+    // Encoded bits not yet written out, right aligned.
+    bits: u32,
+    bit_count: u32,
+    finishing: bool,
+}
+
+impl LzssEncoder {
+    pub const fn new() -> LzssEncoder {
+        LzssEncoder {
+            buffer: [0; 2 * WINDOW_LEN],
+            pos: 0,
+            end: 0,
+            bits: 0,
+            bit_count: 0,
+            finishing: false,
+        }
+    }
+
+    fn push_bits(&mut self, value: u32, count: u32) {
+        self.bits = (self.bits << count) | value;
+        self.bit_count += count;
+    }
+
+    // Writes out whole bytes and returns how many were written.
+    fn write_bytes(&mut self, output: &mut [u8]) -> usize {
+        let mut written = 0;
+        while self.bit_count >= 8 && written < output.len() {
+            self.bit_count -= 8;
+            output[written] = (self.bits >> self.bit_count) as u8;
+            self.bits &= (1 << self.bit_count) - 1;
+            written += 1;
+        }
+        written
+    }
+
+    // Drops bytes which have left the window, to make space for input.
+    fn shift_window(&mut self) {
+        let drop = self.pos.saturating_sub(WINDOW_LEN);
+        if drop > 0 {
+            self.buffer.copy_within(drop..self.end, 0);
+            self.pos -= drop;
+            self.end -= drop;
+        }
+    }
+
+    // Returns the distance and length of the longest match for the bytes at
+    // `pos`, preferring the closest one.
+    fn find_match(&self) -> (usize, usize) {
+        let max_len = core::cmp::min(MAX_MATCH, self.end - self.pos);
+        let mut best = (0, 0);
+        for start in (self.pos.saturating_sub(WINDOW_LEN)..self.pos).rev() {
+            let mut len = 0;
+            // Matches may run into the bytes being encoded, the decoder
+            // copies byte by byte.
+            while len < max_len && self.buffer[start + len] == self.buffer[self.pos + len] {
+                len += 1;
+            }
+            if len > best.1 {
+                best = (self.pos - start, len);
+                if len == max_len {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    fn encode_token(&mut self) {
+        let (distance, len) = self.find_match();
+        if len >= MIN_MATCH {
+            self.push_bits(0, 1);
+            self.push_bits((distance - 1) as u32, WINDOW_BITS);
+            self.push_bits((len - MIN_MATCH) as u32, LENGTH_BITS);
+            self.pos += len;
+        } else {
+            self.push_bits((1 << 8) | self.buffer[self.pos] as u32, LITERAL_TOKEN_BITS);
+            self.pos += 1;
+        }
+    }
+}
+
+impl Compressor for LzssEncoder {
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> (usize, usize) {
+        let mut consumed = 0;
+        let mut produced = 0;
+        loop {
+            produced += self.write_bytes(&mut output[produced..]);
+            if self.bit_count >= 8 {
+                // Output is full.
+                break;
+            }
+
+            if consumed < input.len() && self.end == self.buffer.len() {
+                self.shift_window();
+            }
+            let count = core::cmp::min(self.buffer.len() - self.end, input.len() - consumed);
+            self.buffer[self.end..self.end + count]
+                .copy_from_slice(&input[consumed..consumed + count]);
+            self.end += count;
+            consumed += count;
+
+            // Only encode once a full match length is available, so matches
+            // are not cut short at chunk boundaries.
+            let available = self.end - self.pos;
+            if available >= MAX_MATCH || (self.finishing && available > 0) {
+                self.encode_token();
+            } else {
+                break;
+            }
+        }
+        (consumed, produced)
+    }
+
+    fn finish(&mut self, output: &mut [u8]) -> (usize, bool) {
+        self.finishing = true;
+        let (_, mut produced) = self.compress(&[], output);
+        if self.pos == self.end && self.bit_count > 0 && produced < output.len() {
+            output[produced] = (self.bits << (8 - self.bit_count)) as u8;
+            self.bits = 0;
+            self.bit_count = 0;
+            produced += 1;
+        }
+        (produced, self.pos == self.end && self.bit_count == 0)
+    }
+
+    fn reset(&mut self) {
+        self.pos = 0;
+        self.end = 0;
+        self.bits = 0;
+        self.bit_count = 0;
+        self.finishing = false;
+    }
+}
+
+pub struct LzssDecoder {
+    // Ring buffer of the last decoded bytes.
+    window: [u8; WINDOW_LEN],
+    head: usize,
+    // Input bits not yet decoded, right aligned.
+    bits: u32,
+    bit_count: u32,
+    // Distance and remaining length of the copy being written out.
+    copy: (usize, usize),
+}
+
+impl LzssDecoder {
+    pub const fn new() -> LzssDecoder {
+        LzssDecoder {
+            window: [0; WINDOW_LEN],
+            head: 0,
+            bits: 0,
+            bit_count: 0,
+            copy: (0, 0),
+        }
+    }
+
+    fn take_bits(&mut self, count: u32) -> u32 {
+        self.bit_count -= count;
+        let value = self.bits >> self.bit_count;
+        self.bits &= (1 << self.bit_count) - 1;
+        value
+    }
+
+    fn output_byte(&mut self, byte: u8, output: &mut [u8], produced: &mut usize) {
+        output[*produced] = byte;
+        *produced += 1;
+        self.window[self.head] = byte;
+        self.head = (self.head + 1) % WINDOW_LEN;
+    }
+}
+
+impl Decompressor for LzssDecoder {
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> (usize, usize) {
+        let mut consumed = 0;
+        let mut produced = 0;
+        loop {
+            let (distance, remaining) = self.copy;
+            if remaining > 0 {
+                if produced == output.len() {
+                    break;
+                }
+                let byte = self.window[(self.head + WINDOW_LEN - distance) % WINDOW_LEN];
+                self.output_byte(byte, output, &mut produced);
+                self.copy = (distance, remaining - 1);
+                continue;
+            }
+
+            while self.bit_count <= 24 && consumed < input.len() {
+                self.bits = (self.bits << 8) | input[consumed] as u32;
+                self.bit_count += 8;
+                consumed += 1;
+            }
+
+            if self.bit_count == 0 {
+                break;
+            }
+            if (self.bits >> (self.bit_count - 1)) & 1 == 1 {
+                if self.bit_count < LITERAL_TOKEN_BITS || produced == output.len() {
+                    break;
+                }
+                let byte = self.take_bits(LITERAL_TOKEN_BITS) as u8;
+                self.output_byte(byte, output, &mut produced);
+            } else {
+                if self.bit_count < COPY_TOKEN_BITS {
+                    break;
+                }
+                let token = self.take_bits(COPY_TOKEN_BITS);
+                let distance = ((token >> LENGTH_BITS) & ((1 << WINDOW_BITS) - 1)) as usize + 1;
+                let len = (token & ((1 << LENGTH_BITS) - 1)) as usize + MIN_MATCH;
+                self.copy = (distance, len);
+            }
+        }
+        (consumed, produced)
+    }
+
+    fn is_finished(&self) -> bool {
+        self.copy.1 == 0 && self.bit_count < 8
+    }
+
+    fn reset(&mut self) {
+        self.head = 0;
+        self.bits = 0;
+        self.bit_count = 0;
+        self.copy = (0, 0);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LzssDecoder, LzssEncoder};
+    use crate::compression::{Compressor, Decompressor};
+
+    // Compresses `data` in chunks of `chunk` bytes into `out`, returning the
+    // compressed length.
+    fn compress(data: &[u8], chunk: usize, out: &mut [u8]) -> usize {
+        let mut encoder = LzssEncoder::new();
+        let mut consumed = 0;
+        let mut produced = 0;
+        while consumed < data.len() {
+            let end = core::cmp::min(consumed + chunk, data.len());
+            let (c, p) = encoder.compress(&data[consumed..end], &mut out[produced..]);
+            consumed += c;
+            produced += p;
+        }
+        loop {
+            let (p, done) = encoder.finish(&mut out[produced..]);
+            produced += p;
+            if done {
+                return produced;
+            }
+        }
+    }
+
+    fn decompress(data: &[u8], chunk: usize, out: &mut [u8]) -> usize {
+        let mut decoder = LzssDecoder::new();
+        let mut consumed = 0;
+        let mut produced = 0;
+        while consumed < data.len() {
+            let end = core::cmp::min(consumed + chunk, data.len());
+            let (c, p) = decoder.decompress(&data[consumed..end], &mut out[produced..]);
+            consumed += c;
+            produced += p;
+        }
+        assert!(decoder.is_finished());
+        produced
+    }
+
+    fn round_trip(data: &[u8]) -> usize {
+        let mut compressed = [0; 4096];
+        let mut decompressed = [0; 4096];
+        let mut len = 0;
+        for chunk in [1, 7, 64, data.len().max(1)] {
+            len = compress(data, chunk, &mut compressed);
+            for out_chunk in [1, 13, len.max(1)] {
+                assert_eq!(
+                    decompress(&compressed[..len], out_chunk, &mut decompressed),
+                    data.len()
+                );
+                assert_eq!(&decompressed[..data.len()], data);
+            }
+        }
+        len
+    }
+
+    // Batches of 12 byte sensor records as produced by `sensor_records`:
+    // timestamp, value, sensor id and flags.
+    fn sensor_records(records: &mut [u8]) {
+        for (i, record) in records.chunks_mut(12).enumerate() {
+            let timestamp = 1_000_000 + 5_000 * i as u32;
+            let value = 2150 + (i as i32 % 7) - 3;
+            record[0..4].copy_from_slice(&timestamp.to_le_bytes());
+            record[4..8].copy_from_slice(&value.to_le_bytes());
+            record[8..10].copy_from_slice(&((i % 3) as u16).to_le_bytes());
+            record[10..12].copy_from_slice(&0u16.to_le_bytes());
+        }
+    }
+
+    #[test]
+    fn test_empty() {
+        assert_eq!(round_trip(&[]), 0);
+    }
+
+    #[test]
+    fn test_literals() {
+        let data: [u8; 256] = core::array::from_fn(|i| i as u8);
+        // No repetition: one extra bit per byte.
+        assert_eq!(round_trip(&data), 256 * 9 / 8);
+    }
+
+    #[test]
+    fn test_runs() {
+        let mut data = [0xAA; 1000];
+        data[500..].fill(0x55);
+        assert!(round_trip(&data) < 150);
+    }
+
+    #[test]
+    fn test_overlapping_and_long_input() {
+        let mut data = [0; 2000];
+        let mut state = 0x1234_5678u32;
+        for (i, byte) in data.iter_mut().enumerate() {
+            state = state.wrapping_mul(1103515245).wrapping_add(12345);
+            *byte = if i % 100 < 50 {
+                (state >> 24) as u8
+            } else {
+                b"abcabcabd"[i % 9]
+            };
+        }
+        round_trip(&data);
+    }
+
+    #[test]
+    fn test_sensor_record_ratio() {
+        // One batch of 16 records fits in one UDP datagram.
+        let mut records = [0; 16 * 12];
+        sensor_records(&mut records);
+        let len = round_trip(&records);
+        assert!(len * 100 <= records.len() * 60, "{} bytes", len);
+    }
+}
@@ -0,0 +1,50 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Streaming compression codecs.
+//!
+//! Codecs implement the [`Compressor`] and [`Decompressor`] traits, so that
+//! capsules such as [`compressed_log`](crate::compressed_log) can be used
+//! with any of them. Both traits work on caller-provided buffers and never
+//! allocate: data is passed in chunks of any size, and each call reports how
+//! much input it consumed and how much output it produced.
+
+pub mod lzss;
+
+/// Compresses a stream of bytes.
+pub trait Compressor {
+    /// Compresses bytes from `input` into `output`.
+    ///
+    /// Returns the number of bytes consumed from `input` and the number of
+    /// bytes written to `output`. Not all consumed input produces output
+    /// right away; the codec may keep it buffered until more input arrives
+    /// or [`finish`](Compressor::finish) is called.
+    fn compress(&mut self, input: &[u8], output: &mut [u8]) -> (usize, usize);
+
+    /// Ends the stream and writes out all buffered data.
+    ///
+    /// Returns the number of bytes written to `output` and whether the
+    /// stream is complete. If it is not, `finish` must be called again with
+    /// more output space.
+    fn finish(&mut self, output: &mut [u8]) -> (usize, bool);
+
+    /// Discards all state to start a new stream.
+    fn reset(&mut self);
+}
+
+/// Decompresses a stream of bytes produced by the matching [`Compressor`].
+pub trait Decompressor {
+    /// Decompresses bytes from `input` into `output`.
+    ///
+    /// Returns the number of bytes consumed from `input` and the number of
+    /// bytes written to `output`.
+    fn decompress(&mut self, input: &[u8], output: &mut [u8]) -> (usize, usize);
+
+    /// Returns whether all input consumed so far has been written out, so
+    /// that the stream can end here.
+    fn is_finished(&self) -> bool;
+
+    /// Discards all state to start a new stream.
+    fn reset(&mut self);
+}
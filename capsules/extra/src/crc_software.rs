@@ -0,0 +1,340 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Software implementation of the CRC interface.
+//!
+//! This provides `hil::crc::Crc` for chips without a CRC unit. The CRCs are
+//! computed bitwise, without lookup tables, to keep the flash footprint
+//! small. Their outputs match those of the SAM4L CRCCU, i.e. `Crc32` and
+//! `Crc32C` are the standard CRC-32 and CRC-32C, and `Crc16CCITT` consumes
+//! each input byte from LSB to MSB starting from `0xFFFF`, with no output
+//! post-processing.
+//!
+//! Input is processed from a deferred call, at most [`MAX_CHUNK_LEN`] bytes
+//! at a time. Larger buffers are handed back to the client with the
+//! remaining bytes, which are expected to be passed to
+//! [`Crc::input`] again.
+//!
+//! Usage
+//! -----
+//! ```rust,ignore
+//! let crc_sw = components::crc::CrcSoftwareComponent::new()
+//!     .finalize(components::crc_software_component_static!());
+//! let crc = components::crc::CrcComponent::new(
+//!     board_kernel,
+//!     capsules_extra::crc::DRIVER_NUM,
+//!     crc_sw,
+//! )
+//! .finalize(components::crc_component_static!(
+//!     capsules_extra::crc_software::CrcSoftware<'static>
+//! ));
+//! ```
+
+use core::cell::Cell;
+use kernel::deferred_call::{DeferredCall, DeferredCallClient};
+use kernel::hil::crc::{Client, Crc, CrcAlgorithm, CrcOutput};
+use kernel::utilities::cells::OptionalCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// Maximum number of bytes processed per call to [`Crc::input`].
+pub const MAX_CHUNK_LEN: usize = 256;
+
+/// Returns the initial value of the CRC register, all ones for every
+/// algorithm.
+fn initial_value(algorithm: CrcAlgorithm) -> u32 {
+    match algorithm {
+        CrcAlgorithm::Crc32 | CrcAlgorithm::Crc32C => 0xFFFFFFFF,
+        CrcAlgorithm::Crc16CCITT => 0xFFFF,
+    }
+}
+
+/// Returns the bit-reversed polynomial of `algorithm`.
+fn reversed_poly(algorithm: CrcAlgorithm) -> u32 {
+    match algorithm {
+        CrcAlgorithm::Crc32 => 0xEDB88320,
+        CrcAlgorithm::Crc32C => 0x82F63B78,
+        CrcAlgorithm::Crc16CCITT => 0x8408,
+    }
+}
+
+/// Feeds `data` into the CRC register `crc`, consuming each byte from LSB
+/// to MSB.
+///
+/// The register is kept bit-reversed, so that the input bytes do not need to
+/// be reversed.
+fn update(algorithm: CrcAlgorithm, mut crc: u32, data: &[u8]) -> u32 {
+    let poly = reversed_poly(algorithm);
+    for byte in data {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ poly
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    crc
+}
+
+fn finish(algorithm: CrcAlgorithm, crc: u32) -> CrcOutput {
+    match algorithm {
+        CrcAlgorithm::Crc32 => CrcOutput::Crc32(!crc),
+        CrcAlgorithm::Crc32C => CrcOutput::Crc32C(!crc),
+        CrcAlgorithm::Crc16CCITT => CrcOutput::Crc16CCITT((crc as u16).reverse_bits()),
+    }
+}
+
+pub struct CrcSoftware<'a> {
+    client: OptionalCell<&'a dyn Client>,
+    algorithm: OptionalCell<CrcAlgorithm>,
+    crc: Cell<u32>,
+
+    // Data passed to [`Crc::input`] which is yet to be processed
+    input_data: OptionalCell<SubSliceMut<'static, u8>>,
+    // Marker whether a call to [`Crc::compute`] is pending
+    compute_requested: Cell<bool>,
+
+    deferred_call: DeferredCall,
+}
+
+impl<'a> CrcSoftware<'a> {
+    pub fn new() -> Self {
+        CrcSoftware {
+            client: OptionalCell::empty(),
+            algorithm: OptionalCell::empty(),
+            crc: Cell::new(0),
+            input_data: OptionalCell::empty(),
+            compute_requested: Cell::new(false),
+            deferred_call: DeferredCall::new(),
+        }
+    }
+
+    fn busy(&self) -> bool {
+        self.input_data.is_some() || self.compute_requested.get()
+    }
+}
+
+impl<'a> DeferredCallClient for CrcSoftware<'a> {
+    fn handle_deferred_call(&self) {
+        // The algorithm is always set when input or compute were accepted
+        let algorithm = match self.algorithm.get() {
+            Some(algorithm) => algorithm,
+            None => return,
+        };
+
+        if let Some(mut data) = self.input_data.take() {
+            let len = core::cmp::min(data.len(), MAX_CHUNK_LEN);
+            self.crc
+                .set(update(algorithm, self.crc.get(), &data.as_slice()[..len]));
+            // Hand back the bytes which have not been processed yet
+            data.slice(len..);
+            self.client
+                .map(move |client| client.input_done(Ok(()), data));
+        } else if self.compute_requested.get() {
+            let result = finish(algorithm, self.crc.get());
+
+            // Reset the CRC state such that the next call to input will
+            // start a new CRC
+            self.crc.set(initial_value(algorithm));
+            self.compute_requested.set(false);
+
+            self.client.map(|client| client.crc_done(Ok(result)));
+        }
+    }
+
+    fn register(&'static self) {
+        self.deferred_call.register(self);
+    }
+}
+
+impl<'a> Crc<'a> for CrcSoftware<'a> {
+    fn set_client(&self, client: &'a dyn Client) {
+        self.client.set(client);
+    }
+
+    fn algorithm_supported(&self, algorithm: CrcAlgorithm) -> bool {
+        // Deliberately has an exhaustive list here to avoid
+        // advertising support for added variants to CrcAlgorithm
+        match algorithm {
+            CrcAlgorithm::Crc32 => true,
+            CrcAlgorithm::Crc32C => true,
+            CrcAlgorithm::Crc16CCITT => true,
+        }
+    }
+
+    fn set_algorithm(&self, algorithm: CrcAlgorithm) -> Result<(), ErrorCode> {
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.algorithm.set(algorithm);
+        self.crc.set(initial_value(algorithm));
+
+        Ok(())
+    }
+
+    fn input(
+        &self,
+        data: SubSliceMut<'static, u8>,
+    ) -> Result<(), (ErrorCode, SubSliceMut<'static, u8>)> {
+        if self.algorithm.is_none() {
+            return Err((ErrorCode::RESERVE, data));
+        }
+
+        if self.busy() {
+            return Err((ErrorCode::BUSY, data));
+        }
+
+        self.input_data.set(data);
+        self.deferred_call.set();
+
+        Ok(())
+    }
+
+    fn compute(&self) -> Result<(), ErrorCode> {
+        if self.algorithm.is_none() {
+            return Err(ErrorCode::RESERVE);
+        }
+
+        if self.busy() {
+            return Err(ErrorCode::BUSY);
+        }
+
+        self.compute_requested.set(true);
+        self.deferred_call.set();
+
+        Ok(())
+    }
+
+    fn disable(&self) {
+        // There is no hardware to power down.
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::{finish, initial_value, update, CrcSoftware, MAX_CHUNK_LEN};
+    use core::cell::Cell;
+    use kernel::deferred_call::DeferredCallClient;
+    use kernel::hil::crc::{Client, Crc, CrcAlgorithm, CrcOutput};
+    use kernel::utilities::cells::OptionalCell;
+    use kernel::utilities::leasable_buffer::SubSliceMut;
+    use kernel::ErrorCode;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    const CHECK_INPUT: &[u8] = b"123456789";
+
+    // `CrcOutput` implements neither `PartialEq` nor `Debug`.
+    #[derive(Debug, PartialEq)]
+    enum Output {
+        Crc32(u32),
+        Crc32C(u32),
+        Crc16CCITT(u16),
+    }
+
+    impl From<CrcOutput> for Output {
+        fn from(output: CrcOutput) -> Output {
+            match output {
+                CrcOutput::Crc32(crc) => Output::Crc32(crc),
+                CrcOutput::Crc32C(crc) => Output::Crc32C(crc),
+                CrcOutput::Crc16CCITT(crc) => Output::Crc16CCITT(crc),
+            }
+        }
+    }
+
+    fn crc(algorithm: CrcAlgorithm, data: &[u8]) -> Output {
+        finish(algorithm, update(algorithm, initial_value(algorithm), data)).into()
+    }
+
+    #[test]
+    fn test_check_values() {
+        assert_eq!(
+            crc(CrcAlgorithm::Crc32, CHECK_INPUT),
+            Output::Crc32(0xCBF43926)
+        );
+        assert_eq!(
+            crc(CrcAlgorithm::Crc32C, CHECK_INPUT),
+            Output::Crc32C(0xE3069283)
+        );
+        // Polynomial 0x1021 and initial value 0xFFFF, with every input byte
+        // consumed LSB first and the result not reflected. Computed with a
+        // plain MSB-first CRC-16 over the bit-reversed input bytes.
+        assert_eq!(
+            crc(CrcAlgorithm::Crc16CCITT, CHECK_INPUT),
+            Output::Crc16CCITT(0x89F6)
+        );
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert_eq!(crc(CrcAlgorithm::Crc32, &[]), Output::Crc32(0));
+        assert_eq!(crc(CrcAlgorithm::Crc32C, &[]), Output::Crc32C(0));
+        assert_eq!(
+            crc(CrcAlgorithm::Crc16CCITT, &[]),
+            Output::Crc16CCITT(0xFFFF)
+        );
+    }
+
+    #[derive(Default)]
+    struct TestClient {
+        remaining: OptionalCell<SubSliceMut<'static, u8>>,
+        chunks: Cell<usize>,
+        result: OptionalCell<Output>,
+    }
+
+    impl Client for TestClient {
+        fn input_done(&self, result: Result<(), ErrorCode>, buffer: SubSliceMut<'static, u8>) {
+            assert_eq!(result, Ok(()));
+            self.chunks.set(self.chunks.get() + 1);
+            self.remaining.set(buffer);
+        }
+
+        fn crc_done(&self, result: Result<CrcOutput, ErrorCode>) {
+            self.result.set(result.unwrap().into());
+        }
+    }
+
+    #[test]
+    fn test_chunked_input() {
+        let data: &'static mut [u8] = Box::leak(Box::new([0; 2 * MAX_CHUNK_LEN + 88]));
+        for (i, byte) in data.iter_mut().enumerate() {
+            *byte = (i * 7) as u8;
+        }
+        let expected = crc(CrcAlgorithm::Crc32, data);
+
+        let client = TestClient::default();
+        let crc_sw = CrcSoftware::new();
+        crc_sw.set_client(&client);
+        crc_sw.set_algorithm(CrcAlgorithm::Crc32).unwrap();
+
+        let mut remaining_lens = Vec::new();
+        let mut buffer = SubSliceMut::new(data);
+        while buffer.len() > 0 {
+            assert!(crc_sw.input(buffer).is_ok());
+            assert_eq!(
+                crc_sw.set_algorithm(CrcAlgorithm::Crc32),
+                Err(ErrorCode::BUSY)
+            );
+            crc_sw.handle_deferred_call();
+            buffer = client.remaining.take().unwrap();
+            remaining_lens.push(buffer.len());
+        }
+        assert_eq!(remaining_lens, [MAX_CHUNK_LEN + 88, 88, 0]);
+        assert_eq!(client.chunks.get(), 3);
+
+        assert_eq!(crc_sw.compute(), Ok(()));
+        crc_sw.handle_deferred_call();
+        assert_eq!(client.result.take(), Some(expected));
+
+        // Computing again starts a new CRC over no input.
+        assert_eq!(crc_sw.compute(), Ok(()));
+        crc_sw.handle_deferred_call();
+        assert_eq!(client.result.take(), Some(Output::Crc32(0)));
+    }
+}
@@ -0,0 +1,366 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Reads temperature, humidity and ambient light in one operation.
+//!
+//! Sampling all sensors and transmitting the result is a common pattern for
+//! sensor motes. With this capsule, a process starts all readings with one
+//! command and gets a single upcall once all of them have finished, instead
+//! of one command and upcall per sensor. The readings are started in
+//! parallel. If a combined sensor can only take one reading at a time, such
+//! as temperature and humidity of a BME280, its readings are taken one after
+//! the other.
+//!
+//! Each sensor is optional. The capsule becomes the client of the sensors
+//! it is given, so they cannot also be used by their own syscall drivers.
+//!
+//! The values are written into a read-write allow buffer as a packed
+//! snapshot of [`SNAPSHOT_LEN`] bytes, all fields little endian:
+//!
+//! | Offset | Size | Field                                               |
+//! |--------|------|-----------------------------------------------------|
+//! | 0      | 4    | Valid fields (`u32`, bitmask of the `VALID_*` bits) |
+//! | 4      | 4    | Temperature (`i32`, hundredths of a degree Celsius) |
+//! | 8      | 4    | Relative humidity (`u32`, hundredths of a percent)  |
+//! | 12     | 4    | Ambient light (`u32`, lux)                          |
+//!
+//! A field is only valid if its bit is set, which is not the case if the
+//! sensor is not present or its reading failed.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let environment_snapshot = static_init!(
+//!     capsules_extra::environment_snapshot::EnvironmentSnapshot<'static>,
+//!     capsules_extra::environment_snapshot::EnvironmentSnapshot::new(
+//!         Some(bme280),
+//!         Some(bme280),
+//!         Some(opt3001),
+//!         board_kernel.create_grant(
+//!             capsules_extra::environment_snapshot::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! environment_snapshot.set_sensor_clients();
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::sensors::{
+    AmbientLight, AmbientLightClient, HumidityClient, HumidityDriver, TemperatureClient,
+    TemperatureDriver,
+};
+use kernel::processbuffer::WriteableProcessBuffer;
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::{ErrorCode, ProcessId};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::EnvironmentSnapshot as usize;
+
+/// Size of an encoded snapshot in bytes.
+pub const SNAPSHOT_LEN: usize = 16;
+
+/// The temperature field is valid.
+pub const VALID_TEMPERATURE: u32 = 1 << 0;
+/// The humidity field is valid.
+pub const VALID_HUMIDITY: u32 = 1 << 1;
+/// The ambient light field is valid.
+pub const VALID_AMBIENT_LIGHT: u32 = 1 << 2;
+
+/// Ids for read-write allow buffers
+mod rw_allow {
+    /// Buffer the snapshot is written into
+    pub const SNAPSHOT: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// Sensor values of one snapshot.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Snapshot {
+    pub temperature: Option<i32>,
+    pub humidity: Option<u32>,
+    pub ambient_light: Option<u32>,
+}
+
+impl Snapshot {
+    /// Returns the `VALID_*` bits of the fields which are set.
+    pub fn valid(&self) -> u32 {
+        let mut valid = 0;
+        if self.temperature.is_some() {
+            valid |= VALID_TEMPERATURE;
+        }
+        if self.humidity.is_some() {
+            valid |= VALID_HUMIDITY;
+        }
+        if self.ambient_light.is_some() {
+            valid |= VALID_AMBIENT_LIGHT;
+        }
+        valid
+    }
+
+    /// Writes the snapshot into the first [`SNAPSHOT_LEN`] bytes of `buf`.
+    /// Invalid fields are written as zero.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<(), ErrorCode> {
+        if buf.len() < SNAPSHOT_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        buf[0..4].copy_from_slice(&self.valid().to_le_bytes());
+        buf[4..8].copy_from_slice(&self.temperature.unwrap_or(0).to_le_bytes());
+        buf[8..12].copy_from_slice(&self.humidity.unwrap_or(0).to_le_bytes());
+        buf[12..16].copy_from_slice(&self.ambient_light.unwrap_or(0).to_le_bytes());
+        Ok(())
+    }
+}
+
+/// Per-process state.
+#[derive(Default)]
+pub struct App {
+    // The process is waiting for the current snapshot.
+    waiting: bool,
+}
+
+pub struct EnvironmentSnapshot<'a> {
+    temperature: Option<&'a dyn TemperatureDriver<'a>>,
+    humidity: Option<&'a dyn HumidityDriver<'a>>,
+    ambient_light: Option<&'a dyn AmbientLight<'a>>,
+    /// `VALID_*` bits of the readings which have not finished yet. A
+    /// snapshot is in progress while this is non-zero.
+    pending: Cell<u32>,
+    /// `VALID_*` bits of the pending readings which are waiting for their
+    /// sensor to finish another reading.
+    retry: Cell<u32>,
+    snapshot: Cell<Snapshot>,
+    grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+}
+
+impl<'a> EnvironmentSnapshot<'a> {
+    pub fn new(
+        temperature: Option<&'a dyn TemperatureDriver<'a>>,
+        humidity: Option<&'a dyn HumidityDriver<'a>>,
+        ambient_light: Option<&'a dyn AmbientLight<'a>>,
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<0>, AllowRwCount<{ rw_allow::COUNT }>>,
+    ) -> EnvironmentSnapshot<'a> {
+        EnvironmentSnapshot {
+            temperature,
+            humidity,
+            ambient_light,
+            pending: Cell::new(0),
+            retry: Cell::new(0),
+            snapshot: Cell::new(Snapshot::default()),
+            grant,
+        }
+    }
+
+    /// Makes this capsule the client of all of its sensors.
+    pub fn set_sensor_clients(&'a self) {
+        if let Some(temperature) = self.temperature {
+            temperature.set_client(self);
+        }
+        if let Some(humidity) = self.humidity {
+            humidity.set_client(self);
+        }
+        if let Some(ambient_light) = self.ambient_light {
+            ambient_light.set_client(self);
+        }
+    }
+
+    /// Returns the `VALID_*` bits of the sensors which are present.
+    fn available(&self) -> u32 {
+        let mut available = 0;
+        if self.temperature.is_some() {
+            available |= VALID_TEMPERATURE;
+        }
+        if self.humidity.is_some() {
+            available |= VALID_HUMIDITY;
+        }
+        if self.ambient_light.is_some() {
+            available |= VALID_AMBIENT_LIGHT;
+        }
+        available
+    }
+
+    /// Starts reading all sensors. Returns `ALREADY` if a snapshot is
+    /// already in progress and `NODEVICE` if there are no sensors.
+    fn start(&self) -> Result<(), ErrorCode> {
+        if self.pending.get() != 0 {
+            return Err(ErrorCode::ALREADY);
+        }
+        let available = self.available();
+        if available == 0 {
+            return Err(ErrorCode::NODEVICE);
+        }
+        self.snapshot.set(Snapshot::default());
+        // Mark all readings as pending first, as a sensor may report its
+        // value before the remaining readings have been started.
+        self.pending.set(available);
+        self.start_readings(available);
+        Ok(())
+    }
+
+    /// Starts the readings of the `VALID_*` bits in `fields`. A reading
+    /// which cannot be started is finished without a value.
+    fn start_readings(&self, fields: u32) {
+        for field in [VALID_TEMPERATURE, VALID_HUMIDITY, VALID_AMBIENT_LIGHT] {
+            if fields & field == 0 {
+                continue;
+            }
+            let result = match field {
+                VALID_TEMPERATURE => self.temperature.map(|sensor| sensor.read_temperature()),
+                VALID_HUMIDITY => self.humidity.map(|sensor| sensor.read_humidity()),
+                _ => self
+                    .ambient_light
+                    .map(|sensor| sensor.read_light_intensity()),
+            };
+            match result {
+                Some(Ok(())) => {}
+                // Temperature and humidity are often measured by the same
+                // sensor, which can only take one reading at a time. Retry
+                // once another reading of this snapshot has finished.
+                Some(Err(ErrorCode::BUSY))
+                    if self.pending.get() & !self.retry.get() & !field != 0 =>
+                {
+                    self.retry.set(self.retry.get() | field);
+                }
+                _ => self.reading_done(field),
+            }
+        }
+    }
+
+    /// Marks the reading of `field` as finished, and notifies the waiting
+    /// processes once all readings have finished.
+    fn reading_done(&self, field: u32) {
+        let pending = self.pending.get();
+        if pending & field == 0 {
+            return;
+        }
+        self.pending.set(pending & !field);
+        if pending & !field != 0 {
+            let retry = self.retry.take();
+            if retry != 0 {
+                self.start_readings(retry);
+            }
+            return;
+        }
+
+        let snapshot = self.snapshot.get();
+        for process in self.grant.iter() {
+            process.enter(|app, kernel_data| {
+                if app.waiting {
+                    app.waiting = false;
+                    let result = kernel_data
+                        .get_readwrite_processbuffer(rw_allow::SNAPSHOT)
+                        .and_then(|buffer| {
+                            buffer.mut_enter(|buffer| {
+                                let mut encoded = [0; SNAPSHOT_LEN];
+                                snapshot.encode(&mut encoded)?;
+                                buffer
+                                    .get(0..SNAPSHOT_LEN)
+                                    .ok_or(ErrorCode::SIZE)?
+                                    .copy_from_slice(&encoded);
+                                Ok(())
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE));
+                    kernel_data
+                        .schedule_upcall(
+                            0,
+                            (
+                                kernel::errorcode::into_statuscode(result),
+                                snapshot.valid() as usize,
+                                0,
+                            ),
+                        )
+                        .ok();
+                }
+            });
+        }
+    }
+}
+
+impl TemperatureClient for EnvironmentSnapshot<'_> {
+    fn callback(&self, value: Result<i32, ErrorCode>) {
+        if let Ok(temperature) = value {
+            let mut snapshot = self.snapshot.get();
+            snapshot.temperature = Some(temperature);
+            self.snapshot.set(snapshot);
+        }
+        self.reading_done(VALID_TEMPERATURE);
+    }
+}
+
+impl HumidityClient for EnvironmentSnapshot<'_> {
+    fn callback(&self, value: usize) {
+        let mut snapshot = self.snapshot.get();
+        snapshot.humidity = Some(value as u32);
+        self.snapshot.set(snapshot);
+        self.reading_done(VALID_HUMIDITY);
+    }
+}
+
+impl AmbientLightClient for EnvironmentSnapshot<'_> {
+    fn callback(&self, lux: usize) {
+        let mut snapshot = self.snapshot.get();
+        snapshot.ambient_light = Some(lux as u32);
+        self.snapshot.set(snapshot);
+        self.reading_done(VALID_AMBIENT_LIGHT);
+    }
+}
+
+/// Processes take snapshots with the `allow` and `command` system calls.
+impl SyscallDriver for EnvironmentSnapshot<'_> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Take a snapshot into the read-write allow buffer `0`, which
+    ///   must be at least [`SNAPSHOT_LEN`] bytes long. If a snapshot is
+    ///   already in progress, the process receives the result of that one.
+    ///   Returns `NODEVICE` if the board has no sensors for the capsule.
+    /// - `2`: Returns the `VALID_*` bits of the sensors which are present.
+    ///
+    /// ### Upcall `0`
+    ///
+    /// Signals the end of a snapshot, with the status of writing it into the
+    /// allow buffer and the `VALID_*` bits of the fields which were read.
+    fn command(
+        &self,
+        command_num: usize,
+        _: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => {
+                if self.available() == 0 {
+                    return CommandReturn::failure(ErrorCode::NODEVICE);
+                }
+                // Mark the process first, as the snapshot may complete
+                // synchronously if no sensor reading can be started.
+                if let Err(err) = self.grant.enter(processid, |app, _| app.waiting = true) {
+                    return CommandReturn::failure(err.into());
+                }
+                match self.start() {
+                    Ok(()) | Err(ErrorCode::ALREADY) => CommandReturn::success(),
+                    Err(e) => CommandReturn::failure(e),
+                }
+            }
+
+            2 => CommandReturn::success_u32(self.available()),
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.grant.enter(processid, |_, _| {})
+    }
+}
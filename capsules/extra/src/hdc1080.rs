@@ -0,0 +1,248 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Sensor Driver for the TI HDC1080 Temperature/Humidity sensor
+//! using the I2C bus.
+//!
+//! <https://www.ti.com/lit/ds/symlink/hdc1080.pdf>
+//!
+//! Driver Semantics
+//! ----------------
+//!
+//! This driver exposes the HDC1080's temperature and humidity functionality
+//! via the [TemperatureDriver] and [HumidityDriver] HIL interfaces. The sensor
+//! is configured to acquire both values in sequence, so if the driver receives
+//! a request for either temperature or humidity while a request for the other
+//! is outstanding, both are returned to their respective clients from a single
+//! measurement.
+//!
+//! The HDC1080 has no data ready pin, so the end of a conversion is detected
+//! by waiting for the maximum conversion time with an alarm before reading
+//! the result.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let hdc1080 = components::hdc1080::Hdc1080Component::new(
+//!     mux_i2c,
+//!     capsules_extra::hdc1080::BASE_ADDR,
+//!     mux_alarm,
+//! )
+//! .finalize(components::hdc1080_component_static!(
+//!     nrf52::rtc::Rtc<'static>,
+//!     nrf52840::i2c::TWI
+//! ));
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::i2c::{self, I2CClient, I2CDevice};
+use kernel::hil::sensors::{HumidityClient, HumidityDriver, TemperatureClient, TemperatureDriver};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+pub static BASE_ADDR: u8 = 0x40;
+
+/// Buffer size required by the driver.
+pub const BUF_LEN: usize = 4;
+
+enum Registers {
+    /// Temperature result, followed by the humidity result when reading four
+    /// bytes in sequential mode
+    Temperature = 0x00,
+    Configuration = 0x02,
+}
+
+/// Configuration register value: acquire temperature and humidity in
+/// sequence, both with 14 bit resolution, heater off.
+const CONFIG_SEQUENTIAL_14BIT: u16 = 1 << 12;
+
+/// Time needed for a sequential 14 bit temperature and humidity conversion
+/// (6.35 ms + 6.5 ms), rounded up.
+const CONVERSION_TIME_MS: u32 = 15;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum State {
+    Idle,
+    /// Writing the configuration register, done once before the first
+    /// measurement.
+    Configure,
+    /// Setting the register pointer to the temperature register, which
+    /// triggers a measurement.
+    Trigger,
+    /// Waiting for the conversion to finish.
+    Convert,
+    /// Reading back temperature and humidity.
+    Read,
+}
+
+pub struct Hdc1080<'a, A: Alarm<'a>, I: I2CDevice> {
+    i2c: &'a I,
+    alarm: &'a A,
+    buffer: TakeCell<'static, [u8]>,
+    temperature_client: OptionalCell<&'a dyn TemperatureClient>,
+    humidity_client: OptionalCell<&'a dyn HumidityClient>,
+    state: Cell<State>,
+    configured: Cell<bool>,
+    pending_temperature: Cell<bool>,
+    pending_humidity: Cell<bool>,
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> Hdc1080<'a, A, I> {
+    pub fn new(i2c: &'a I, alarm: &'a A, buffer: &'static mut [u8]) -> Self {
+        Hdc1080 {
+            i2c,
+            alarm,
+            buffer: TakeCell::new(buffer),
+            temperature_client: OptionalCell::empty(),
+            humidity_client: OptionalCell::empty(),
+            state: Cell::new(State::Idle),
+            configured: Cell::new(false),
+            pending_temperature: Cell::new(false),
+            pending_humidity: Cell::new(false),
+        }
+    }
+
+    fn start_reading(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            // The pending request is served by the ongoing measurement.
+            return Ok(());
+        }
+        self.buffer.take().map_or(Err(ErrorCode::BUSY), |buffer| {
+            self.i2c.enable();
+            let (state, len) = if self.configured.get() {
+                buffer[0] = Registers::Temperature as u8;
+                (State::Trigger, 1)
+            } else {
+                buffer[0] = Registers::Configuration as u8;
+                buffer[1..3].copy_from_slice(&CONFIG_SEQUENTIAL_14BIT.to_be_bytes());
+                (State::Configure, 3)
+            };
+            match self.i2c.write(buffer, len) {
+                Ok(()) => {
+                    self.state.set(state);
+                    Ok(())
+                }
+                Err((error, buffer)) => {
+                    self.buffer.replace(buffer);
+                    self.i2c.disable();
+                    Err(error.into())
+                }
+            }
+        })
+    }
+
+    /// Reports `result` to all clients with an outstanding request.
+    fn report(&self, result: Result<(i32, usize), ErrorCode>) {
+        self.state.set(State::Idle);
+        self.i2c.disable();
+        if self.pending_temperature.get() {
+            self.pending_temperature.set(false);
+            self.temperature_client
+                .map(|client| client.callback(result.map(|(temperature, _)| temperature)));
+        }
+        if self.pending_humidity.get() {
+            self.pending_humidity.set(false);
+            // The humidity HIL has no way to signal an error.
+            let humidity = result.map_or(usize::MAX, |(_, humidity)| humidity);
+            self.humidity_client.map(|client| client.callback(humidity));
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> TemperatureDriver<'a> for Hdc1080<'a, A, I> {
+    fn set_client(&self, client: &'a dyn TemperatureClient) {
+        self.temperature_client.set(client);
+    }
+
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        if self.pending_temperature.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let result = self.start_reading();
+        if result.is_ok() {
+            self.pending_temperature.set(true);
+        }
+        result
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> HumidityDriver<'a> for Hdc1080<'a, A, I> {
+    fn set_client(&self, client: &'a dyn HumidityClient) {
+        self.humidity_client.set(client);
+    }
+
+    fn read_humidity(&self) -> Result<(), ErrorCode> {
+        if self.pending_humidity.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let result = self.start_reading();
+        if result.is_ok() {
+            self.pending_humidity.set(true);
+        }
+        result
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> AlarmClient for Hdc1080<'a, A, I> {
+    fn alarm(&self) {
+        if self.state.get() != State::Convert {
+            return;
+        }
+        if let Some(buffer) = self.buffer.take() {
+            if let Err((error, buffer)) = self.i2c.read(buffer, 4) {
+                self.buffer.replace(buffer);
+                self.report(Err(error.into()));
+            } else {
+                self.state.set(State::Read);
+            }
+        }
+    }
+}
+
+impl<'a, A: Alarm<'a>, I: I2CDevice> I2CClient for Hdc1080<'a, A, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), i2c::Error>) {
+        if let Err(i2c_err) = status {
+            self.buffer.replace(buffer);
+            self.report(Err(i2c_err.into()));
+            return;
+        }
+
+        match self.state.get() {
+            State::Configure => {
+                self.configured.set(true);
+                buffer[0] = Registers::Temperature as u8;
+                if let Err((error, buffer)) = self.i2c.write(buffer, 1) {
+                    self.buffer.replace(buffer);
+                    self.report(Err(error.into()));
+                } else {
+                    self.state.set(State::Trigger);
+                }
+            }
+            State::Trigger => {
+                self.buffer.replace(buffer);
+                self.state.set(State::Convert);
+                let interval = self.alarm.ticks_from_ms(CONVERSION_TIME_MS);
+                self.alarm.set_alarm(self.alarm.now(), interval);
+            }
+            State::Read => {
+                let temperature_raw = u16::from_be_bytes([buffer[0], buffer[1]]) as i32;
+                let humidity_raw = u16::from_be_bytes([buffer[2], buffer[3]]) as usize;
+                self.buffer.replace(buffer);
+
+                // T = raw / 2^16 * 165 - 40 [degrees C], in hundredths of a
+                // degree.
+                let temperature = ((temperature_raw * 16500) >> 16) - 4000;
+                // RH = raw / 2^16 * 100 [%], in hundredths of a percent.
+                let humidity = (humidity_raw * 10000) >> 16;
+
+                self.report(Ok((temperature, humidity)));
+            }
+            State::Idle | State::Convert => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
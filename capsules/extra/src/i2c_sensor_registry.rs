@@ -0,0 +1,708 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Detects which I2C sensors are present at boot.
+//!
+//! Sensor boards of the same family often differ only in which sensors are
+//! fitted. With this capsule, one kernel image supports all of them: the
+//! board creates drivers for every sensor that may be fitted, and the
+//! registry probes a static table of those sensors at boot. Each sensor is
+//! identified by reading one of its registers, not just by whether its
+//! address is acknowledged, as the SHT3x and the OPT3001 share the
+//! addresses 0x44 and 0x45. Once an address has been identified, later
+//! entries for the same address are skipped, so the table should list the
+//! preferred sensor first.
+//!
+//! The registry implements the `TemperatureDriver`, `HumidityDriver` and
+//! `AmbientLight` HILs by forwarding them to the first detected sensor in
+//! the table which provides the reading. The usual syscall drivers are then
+//! created on top of the registry. Readings fail with `BUSY` until the
+//! probe has finished, and with `NODEVICE` if no detected sensor provides
+//! them.
+//!
+//! The detected sensors are printed on the debug console once the probe has
+//! finished, and processes can list them through the syscall interface.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let sensor_registry = components::i2c_sensor_registry::I2CSensorRegistryComponent::new(
+//!     mux_i2c,
+//!     [
+//!         Sensor::opt3001(0x44, opt3001),
+//!         Sensor::sht3x(0x44, sht3x),
+//!         Sensor::bme280(0x76, bme280),
+//!     ],
+//! )
+//! .finalize(components::i2c_sensor_registry_component_static!(
+//!     nrf52840::i2c::TWI,
+//!     3
+//! ));
+//! let temperature = components::temperature::TemperatureComponent::new(
+//!     board_kernel,
+//!     capsules_extra::temperature::DRIVER_NUM,
+//!     sensor_registry,
+//! )
+//! .finalize(components::temperature_component_static!(
+//!     I2CSensorRegistryComponentType<nrf52840::i2c::TWI>
+//! ));
+//! ```
+
+use core::cell::Cell;
+
+use kernel::debug;
+use kernel::hil::i2c::{Error, I2CClient, I2CDevice};
+use kernel::hil::sensors::{
+    AmbientLight, AmbientLightClient, HumidityClient, HumidityDriver, TemperatureClient,
+    TemperatureDriver,
+};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::{ErrorCode, ProcessId};
+
+use crate::sht3x::crc8;
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::I2CSensorRegistry as usize;
+
+/// Recommended buffer length.
+pub const BUF_LEN: usize = 3;
+
+/// OPT3001 device ID register and its value.
+const OPT3001_DEVICE_ID: u8 = 0x7F;
+const OPT3001_DEVICE_ID_VALUE: u16 = 0x3001;
+
+/// BME280 chip ID register and its value.
+const BME280_CHIP_ID: u8 = 0xD0;
+const BME280_CHIP_ID_VALUE: u8 = 0x60;
+
+/// SHT3x command to read out the status register.
+const SHT3X_READ_STATUS: u16 = 0xF32D;
+
+/// Sensors which the registry can identify.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SensorKind {
+    /// Sensirion SHT3x temperature and humidity sensor.
+    Sht3x = 0,
+    /// Bosch BME280 temperature, humidity and pressure sensor.
+    Bme280 = 1,
+    /// TI OPT3001 ambient light sensor.
+    Opt3001 = 2,
+}
+
+impl SensorKind {
+    fn name(self) -> &'static str {
+        match self {
+            SensorKind::Sht3x => "SHT3x",
+            SensorKind::Bme280 => "BME280",
+            SensorKind::Opt3001 => "OPT3001",
+        }
+    }
+}
+
+/// A sensor which may be fitted, and the drivers used if it is detected.
+#[derive(Copy, Clone)]
+pub struct Sensor<'a> {
+    pub kind: SensorKind,
+    pub address: u8,
+    pub temperature: Option<&'a dyn TemperatureDriver<'a>>,
+    pub humidity: Option<&'a dyn HumidityDriver<'a>>,
+    pub ambient_light: Option<&'a dyn AmbientLight<'a>>,
+}
+
+impl<'a> Sensor<'a> {
+    pub fn sht3x<S: TemperatureDriver<'a> + HumidityDriver<'a>>(address: u8, sht3x: &'a S) -> Self {
+        Sensor {
+            kind: SensorKind::Sht3x,
+            address,
+            temperature: Some(sht3x),
+            humidity: Some(sht3x),
+            ambient_light: None,
+        }
+    }
+
+    pub fn bme280<S: TemperatureDriver<'a> + HumidityDriver<'a>>(
+        address: u8,
+        bme280: &'a S,
+    ) -> Self {
+        Sensor {
+            kind: SensorKind::Bme280,
+            address,
+            temperature: Some(bme280),
+            humidity: Some(bme280),
+            ambient_light: None,
+        }
+    }
+
+    pub fn opt3001<S: AmbientLight<'a>>(address: u8, opt3001: &'a S) -> Self {
+        Sensor {
+            kind: SensorKind::Opt3001,
+            address,
+            temperature: None,
+            humidity: None,
+            ambient_light: Some(opt3001),
+        }
+    }
+}
+
+/// An entry of the registry's table: a sensor and the I2C device, with the
+/// sensor's address, used to probe for it.
+pub struct SensorEntry<'a, I: I2CDevice> {
+    sensor: Sensor<'a>,
+    device: &'a I,
+    detected: Cell<bool>,
+}
+
+impl<'a, I: I2CDevice> SensorEntry<'a, I> {
+    pub fn new(sensor: Sensor<'a>, device: &'a I) -> Self {
+        SensorEntry {
+            sensor,
+            device,
+            detected: Cell::new(false),
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    /// Reading the identification register of the sensor at this index.
+    Identifying(usize),
+    /// Reading the status of the SHT3x at this index, after sending the
+    /// command.
+    ReadingStatus(usize),
+    Done,
+}
+
+pub struct I2CSensorRegistry<'a, I: I2CDevice> {
+    sensors: &'a [SensorEntry<'a, I>],
+    buffer: TakeCell<'static, [u8]>,
+    state: Cell<State>,
+
+    temperature: OptionalCell<&'a dyn TemperatureDriver<'a>>,
+    humidity: OptionalCell<&'a dyn HumidityDriver<'a>>,
+    ambient_light: OptionalCell<&'a dyn AmbientLight<'a>>,
+
+    temperature_client: OptionalCell<&'a dyn TemperatureClient>,
+    humidity_client: OptionalCell<&'a dyn HumidityClient>,
+    ambient_light_client: OptionalCell<&'a dyn AmbientLightClient>,
+}
+
+impl<'a, I: I2CDevice> I2CSensorRegistry<'a, I> {
+    pub fn new(sensors: &'a [SensorEntry<'a, I>], buffer: &'static mut [u8]) -> Self {
+        I2CSensorRegistry {
+            sensors,
+            buffer: TakeCell::new(buffer),
+            state: Cell::new(State::Idle),
+            temperature: OptionalCell::empty(),
+            humidity: OptionalCell::empty(),
+            ambient_light: OptionalCell::empty(),
+            temperature_client: OptionalCell::empty(),
+            humidity_client: OptionalCell::empty(),
+            ambient_light_client: OptionalCell::empty(),
+        }
+    }
+
+    /// Probes for all sensors in the table, one after another.
+    pub fn probe(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.probe_from(0);
+        Ok(())
+    }
+
+    /// Returns the sensors which were detected, in table order.
+    pub fn detected(&self) -> impl Iterator<Item = &Sensor<'a>> {
+        self.sensors
+            .iter()
+            .filter(|entry| entry.detected.get())
+            .map(|entry| &entry.sensor)
+    }
+
+    /// Starts probing for the first sensor from `index` onwards whose address
+    /// has not already been identified.
+    fn probe_from(&self, index: usize) {
+        for (index, entry) in self.sensors.iter().enumerate().skip(index) {
+            let address = entry.sensor.address;
+            if self.detected().any(|sensor| sensor.address == address) {
+                continue;
+            }
+            let Some(buffer) = self.buffer.take() else {
+                break;
+            };
+
+            entry.device.enable();
+            let result = match entry.sensor.kind {
+                SensorKind::Sht3x => {
+                    buffer[0..2].copy_from_slice(&SHT3X_READ_STATUS.to_be_bytes());
+                    entry.device.write(buffer, 2)
+                }
+                SensorKind::Bme280 => {
+                    buffer[0] = BME280_CHIP_ID;
+                    entry.device.write_read(buffer, 1, 1)
+                }
+                SensorKind::Opt3001 => {
+                    buffer[0] = OPT3001_DEVICE_ID;
+                    entry.device.write_read(buffer, 1, 2)
+                }
+            };
+            match result {
+                Ok(()) => {
+                    self.state.set(State::Identifying(index));
+                    return;
+                }
+                Err((_error, buffer)) => {
+                    entry.device.disable();
+                    self.buffer.replace(buffer);
+                }
+            }
+        }
+        self.finish();
+    }
+
+    fn probe_done(&self, index: usize, buffer: &'static mut [u8], detected: bool) {
+        let entry = &self.sensors[index];
+        entry.device.disable();
+        entry.detected.set(detected);
+        self.buffer.replace(buffer);
+        self.probe_from(index + 1);
+    }
+
+    /// Selects the detected sensor for each reading and reports the detected
+    /// sensors.
+    fn finish(&self) {
+        self.state.set(State::Done);
+        for sensor in self.detected() {
+            if let Some(temperature) = sensor.temperature.filter(|_| self.temperature.is_none()) {
+                self.temperature_client
+                    .map(|client| temperature.set_client(client));
+                self.temperature.set(temperature);
+            }
+            if let Some(humidity) = sensor.humidity.filter(|_| self.humidity.is_none()) {
+                self.humidity_client
+                    .map(|client| humidity.set_client(client));
+                self.humidity.set(humidity);
+            }
+            if let Some(ambient_light) = sensor
+                .ambient_light
+                .filter(|_| self.ambient_light.is_none())
+            {
+                self.ambient_light_client
+                    .map(|client| ambient_light.set_client(client));
+                self.ambient_light.set(ambient_light);
+            }
+        }
+        self.report();
+    }
+
+    /// Prints the detected sensors on the debug console.
+    fn report(&self) {
+        // The unit tests run without a debug writer
+        if cfg!(test) {
+            return;
+        }
+        for sensor in self.detected() {
+            debug!(
+                "Detected {} at I2C address {:#04x}",
+                sensor.kind.name(),
+                sensor.address
+            );
+        }
+        if self.detected().next().is_none() {
+            debug!("No I2C sensors detected");
+        }
+    }
+
+    fn not_ready(&self) -> ErrorCode {
+        if self.state.get() == State::Done {
+            ErrorCode::NODEVICE
+        } else {
+            ErrorCode::BUSY
+        }
+    }
+}
+
+impl<I: I2CDevice> I2CClient for I2CSensorRegistry<'_, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        match self.state.get() {
+            State::Identifying(index) => {
+                if status.is_err() {
+                    self.probe_done(index, buffer, false);
+                    return;
+                }
+                match self.sensors[index].sensor.kind {
+                    SensorKind::Sht3x => {
+                        // The status is two bytes followed by their CRC
+                        match self.sensors[index].device.read(buffer, 3) {
+                            Ok(()) => self.state.set(State::ReadingStatus(index)),
+                            Err((_error, buffer)) => self.probe_done(index, buffer, false),
+                        }
+                    }
+                    SensorKind::Bme280 => {
+                        let detected = buffer[0] == BME280_CHIP_ID_VALUE;
+                        self.probe_done(index, buffer, detected);
+                    }
+                    SensorKind::Opt3001 => {
+                        let device_id = u16::from_be_bytes([buffer[0], buffer[1]]);
+                        let detected = device_id == OPT3001_DEVICE_ID_VALUE;
+                        self.probe_done(index, buffer, detected);
+                    }
+                }
+            }
+            State::ReadingStatus(index) => {
+                let detected = status.is_ok() && crc8(&buffer[0..2]) == buffer[2];
+                self.probe_done(index, buffer, detected);
+            }
+            State::Idle | State::Done => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
+
+impl<'a, I: I2CDevice> TemperatureDriver<'a> for I2CSensorRegistry<'a, I> {
+    fn set_client(&self, client: &'a dyn TemperatureClient) {
+        self.temperature_client.set(client);
+        self.temperature
+            .map(|temperature| temperature.set_client(client));
+    }
+
+    fn read_temperature(&self) -> Result<(), ErrorCode> {
+        self.temperature
+            .map_or(Err(self.not_ready()), |temperature| {
+                temperature.read_temperature()
+            })
+    }
+}
+
+impl<'a, I: I2CDevice> HumidityDriver<'a> for I2CSensorRegistry<'a, I> {
+    fn set_client(&self, client: &'a dyn HumidityClient) {
+        self.humidity_client.set(client);
+        self.humidity.map(|humidity| humidity.set_client(client));
+    }
+
+    fn read_humidity(&self) -> Result<(), ErrorCode> {
+        self.humidity
+            .map_or(Err(self.not_ready()), |humidity| humidity.read_humidity())
+    }
+}
+
+impl<'a, I: I2CDevice> AmbientLight<'a> for I2CSensorRegistry<'a, I> {
+    fn set_client(&self, client: &'a dyn AmbientLightClient) {
+        self.ambient_light_client.set(client);
+        self.ambient_light
+            .map(|ambient_light| ambient_light.set_client(client));
+    }
+
+    fn read_light_intensity(&self) -> Result<(), ErrorCode> {
+        self.ambient_light
+            .map_or(Err(self.not_ready()), |ambient_light| {
+                ambient_light.read_light_intensity()
+            })
+    }
+}
+
+impl<I: I2CDevice> SyscallDriver for I2CSensorRegistry<'_, I> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Returns the number of detected sensors, or `BUSY` if the probe
+    ///   has not finished yet.
+    /// - `2`: Returns the kind (`0`: SHT3x, `1`: BME280, `2`: OPT3001) and the
+    ///   I2C address of the detected sensor with index `data1`.
+    fn command(&self, command_num: usize, data1: usize, _: usize, _: ProcessId) -> CommandReturn {
+        if command_num != 0 && self.state.get() != State::Done {
+            return CommandReturn::failure(ErrorCode::BUSY);
+        }
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => CommandReturn::success_u32(self.detected().count() as u32),
+
+            2 => match self.detected().nth(data1) {
+                Some(sensor) => {
+                    CommandReturn::success_u32_u32(sensor.kind as u32, sensor.address as u32)
+                }
+                None => CommandReturn::failure(ErrorCode::INVAL),
+            },
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, _processid: ProcessId) -> Result<(), kernel::process::Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use std::boxed::Box;
+    use std::vec::Vec;
+
+    /// An I2C device which holds the buffer of its pending operation until
+    /// the test completes it.
+    struct FakeI2CDevice {
+        pending: TakeCell<'static, [u8]>,
+        /// Bytes written by the pending operation.
+        written: Cell<[u8; 2]>,
+        enabled: Cell<bool>,
+    }
+
+    impl FakeI2CDevice {
+        fn new() -> Self {
+            FakeI2CDevice {
+                pending: TakeCell::empty(),
+                written: Cell::new([0; 2]),
+                enabled: Cell::new(false),
+            }
+        }
+
+        fn start(&self, buffer: &'static mut [u8], write_len: usize) {
+            let mut written = [0; 2];
+            written[..write_len].copy_from_slice(&buffer[..write_len]);
+            self.written.set(written);
+            self.pending.replace(buffer);
+        }
+    }
+
+    impl I2CDevice for FakeI2CDevice {
+        fn enable(&self) {
+            self.enabled.set(true);
+        }
+
+        fn disable(&self) {
+            self.enabled.set(false);
+        }
+
+        fn write_read(
+            &self,
+            data: &'static mut [u8],
+            write_len: usize,
+            _read_len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            self.start(data, write_len);
+            Ok(())
+        }
+
+        fn write(
+            &self,
+            data: &'static mut [u8],
+            len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            self.start(data, len);
+            Ok(())
+        }
+
+        fn read(
+            &self,
+            buffer: &'static mut [u8],
+            _len: usize,
+        ) -> Result<(), (Error, &'static mut [u8])> {
+            self.start(buffer, 0);
+            Ok(())
+        }
+    }
+
+    /// A sensor driver which counts the readings started.
+    #[derive(Default)]
+    struct FakeSensor {
+        reads: Cell<usize>,
+        temperature_client: OptionalCell<&'static dyn TemperatureClient>,
+        ambient_light_client: OptionalCell<&'static dyn AmbientLightClient>,
+    }
+
+    impl TemperatureDriver<'static> for FakeSensor {
+        fn set_client(&self, client: &'static dyn TemperatureClient) {
+            self.temperature_client.set(client);
+        }
+
+        fn read_temperature(&self) -> Result<(), ErrorCode> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(())
+        }
+    }
+
+    impl HumidityDriver<'static> for FakeSensor {
+        fn set_client(&self, _client: &'static dyn HumidityClient) {}
+
+        fn read_humidity(&self) -> Result<(), ErrorCode> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(())
+        }
+    }
+
+    impl AmbientLight<'static> for FakeSensor {
+        fn set_client(&self, client: &'static dyn AmbientLightClient) {
+            self.ambient_light_client.set(client);
+        }
+
+        fn read_light_intensity(&self) -> Result<(), ErrorCode> {
+            self.reads.set(self.reads.get() + 1);
+            Ok(())
+        }
+    }
+
+    struct FakeClient;
+
+    impl TemperatureClient for FakeClient {
+        fn callback(&self, _value: Result<i32, ErrorCode>) {}
+    }
+
+    impl AmbientLightClient for FakeClient {
+        fn callback(&self, _lux: usize) {}
+    }
+
+    struct Board {
+        registry: &'static I2CSensorRegistry<'static, FakeI2CDevice>,
+        devices: Vec<&'static FakeI2CDevice>,
+        opt3001: &'static FakeSensor,
+        sht3x: &'static FakeSensor,
+        bme280: &'static FakeSensor,
+    }
+
+    /// Creates a registry probing for an OPT3001 and an SHT3x at 0x44, and a
+    /// BME280 at 0x76.
+    fn board() -> Board {
+        let opt3001: &'static FakeSensor = Box::leak(Box::default());
+        let sht3x: &'static FakeSensor = Box::leak(Box::default());
+        let bme280: &'static FakeSensor = Box::leak(Box::default());
+        let sensors = [
+            Sensor::opt3001(0x44, opt3001),
+            Sensor::sht3x(0x44, sht3x),
+            Sensor::bme280(0x76, bme280),
+        ];
+        let devices: Vec<&'static FakeI2CDevice> = sensors
+            .iter()
+            .map(|_| &*Box::leak(Box::new(FakeI2CDevice::new())))
+            .collect();
+        let entries: Vec<SensorEntry<'static, FakeI2CDevice>> = sensors
+            .iter()
+            .zip(devices.iter())
+            .map(|(sensor, device)| SensorEntry::new(*sensor, *device))
+            .collect();
+        let registry = Box::leak(Box::new(I2CSensorRegistry::new(
+            entries.leak(),
+            Box::leak(Box::new([0; BUF_LEN])),
+        )));
+        Board {
+            registry,
+            devices,
+            opt3001,
+            sht3x,
+            bme280,
+        }
+    }
+
+    impl Board {
+        /// Completes the pending operation of the device with index `device`
+        /// with `data`, or with a NACK if `data` is `None`. Returns the bytes
+        /// written by the operation.
+        fn complete(&self, device: usize, data: Option<&[u8]>) -> [u8; 2] {
+            let device = self.devices[device];
+            assert!(device.enabled.get());
+            let buffer = device.pending.take().expect("no pending operation");
+            let status = match data {
+                Some(data) => {
+                    buffer[..data.len()].copy_from_slice(data);
+                    Ok(())
+                }
+                None => Err(Error::AddressNak),
+            };
+            let written = device.written.get();
+            self.registry.command_complete(buffer, status);
+            written
+        }
+
+        fn detected(&self) -> Vec<(SensorKind, u8)> {
+            self.registry
+                .detected()
+                .map(|sensor| (sensor.kind, sensor.address))
+                .collect()
+        }
+
+        fn assert_idle(&self) {
+            for device in &self.devices {
+                assert!(device.pending.is_none());
+                assert!(!device.enabled.get());
+            }
+        }
+    }
+
+    #[test]
+    fn test_detect_opt3001() {
+        let board = board();
+        let client: &'static FakeClient = &FakeClient;
+        AmbientLight::set_client(board.registry, client);
+        board.registry.probe().unwrap();
+        assert_eq!(board.registry.read_light_intensity(), Err(ErrorCode::BUSY));
+
+        assert_eq!(board.complete(0, Some(&[0x30, 0x01])), [0x7F, 0]);
+        // The SHT3x shares the OPT3001's address, so it is not probed
+        assert_eq!(board.complete(2, None), [0xD0, 0]);
+        board.assert_idle();
+
+        assert_eq!(board.detected(), [(SensorKind::Opt3001, 0x44)]);
+        assert!(board.opt3001.ambient_light_client.is_some());
+        assert_eq!(board.registry.read_light_intensity(), Ok(()));
+        assert_eq!(board.opt3001.reads.get(), 1);
+        assert_eq!(board.registry.read_temperature(), Err(ErrorCode::NODEVICE));
+        assert_eq!(board.registry.probe(), Err(ErrorCode::ALREADY));
+    }
+
+    #[test]
+    fn test_detect_sht3x_and_bme280() {
+        let board = board();
+        board.registry.probe().unwrap();
+
+        // The SHT3x does not acknowledge a read without a command
+        board.complete(0, None);
+        assert_eq!(board.complete(1, Some(&[])), [0xF3, 0x2D]);
+        let status = [0x80, 0x10];
+        board.complete(1, Some(&[status[0], status[1], crc8(&status)]));
+        board.complete(2, Some(&[0x60]));
+        board.assert_idle();
+
+        assert_eq!(
+            board.detected(),
+            [(SensorKind::Sht3x, 0x44), (SensorKind::Bme280, 0x76)]
+        );
+
+        // Readings go to the first sensor in the table providing them
+        let client: &'static FakeClient = &FakeClient;
+        TemperatureDriver::set_client(board.registry, client);
+        assert!(board.sht3x.temperature_client.is_some());
+        assert!(board.bme280.temperature_client.is_none());
+        assert_eq!(board.registry.read_temperature(), Ok(()));
+        assert_eq!(board.registry.read_humidity(), Ok(()));
+        assert_eq!(board.sht3x.reads.get(), 2);
+        assert_eq!(board.bme280.reads.get(), 0);
+    }
+
+    #[test]
+    fn test_reject_wrong_identification() {
+        let board = board();
+        board.registry.probe().unwrap();
+
+        // A different device at the OPT3001's address
+        board.complete(0, Some(&[0x12, 0x34]));
+        // SHT3x status with a wrong CRC
+        board.complete(1, Some(&[]));
+        board.complete(1, Some(&[0x80, 0x10, 0x00]));
+        // A BMP280 instead of a BME280
+        board.complete(2, Some(&[0x58]));
+        board.assert_idle();
+
+        assert!(board.detected().is_empty());
+        assert_eq!(board.registry.read_humidity(), Err(ErrorCode::NODEVICE));
+    }
+}
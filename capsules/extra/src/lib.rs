@@ -28,22 +28,28 @@ pub mod buzzer_driver;
 pub mod buzzer_pwm;
 pub mod can;
 pub mod ccs811;
+pub mod compressed_log;
+pub mod compression;
 pub mod crc;
+pub mod crc_software;
 pub mod cycle_count;
 pub mod dac;
 pub mod date_time;
 pub mod debug_process_restart;
+pub mod environment_snapshot;
 pub mod eui64;
 pub mod fm25cl;
 pub mod ft6x06;
 pub mod fxos8700cq;
 pub mod gpio_async;
 pub mod hd44780;
+pub mod hdc1080;
 pub mod hmac;
 pub mod hmac_sha256;
 pub mod hs3003;
 pub mod hts221;
 pub mod humidity;
+pub mod i2c_sensor_registry;
 pub mod ieee802154;
 pub mod isl29035;
 pub mod kv_driver;
@@ -64,9 +70,11 @@ pub mod mcp230xx;
 pub mod mlx90614;
 pub mod mx25r6435f;
 pub mod ninedof;
+pub mod nonvolatile_counters;
 pub mod nonvolatile_storage_driver;
 pub mod nonvolatile_to_pages;
 pub mod nrf51822_serialization;
+pub mod opt3001;
 pub mod panic_button;
 pub mod pca9544a;
 pub mod pressure;
@@ -80,6 +88,7 @@ pub mod screen;
 pub mod screen_shared;
 pub mod sdcard;
 pub mod segger_rtt;
+pub mod sensor_records;
 pub mod seven_segment;
 pub mod sh1106;
 pub mod sha;
@@ -174,35 +174,34 @@ impl ICMP6Header {
 
         let (off, code) = dec_try!(buf, off; decode_u8);
         icmp_header.set_code(code);
+        // `decode_u16`/`decode_u32` already convert from network byte order
         let (off, cksum) = dec_try!(buf, off; decode_u16);
-        icmp_header.set_cksum(u16::from_be(cksum));
+        icmp_header.set_cksum(cksum);
 
-        match icmp_type {
+        let off = match icmp_type {
             ICMP6Type::Type1 => {
-                let (_off, unused) = dec_try!(buf, off; decode_u32);
-                let unused = u32::from_be(unused);
+                let (off, unused) = dec_try!(buf, off; decode_u32);
                 icmp_header.set_options(ICMP6HeaderOptions::Type1 { unused });
+                off
             }
             ICMP6Type::Type3 => {
-                let (_off, unused) = dec_try!(buf, off; decode_u32);
-                let unused = u32::from_be(unused);
+                let (off, unused) = dec_try!(buf, off; decode_u32);
                 icmp_header.set_options(ICMP6HeaderOptions::Type3 { unused });
+                off
             }
             ICMP6Type::Type128 => {
-                let (_off, id) = dec_try!(buf, off; decode_u16);
-                let id = u16::from_be(id);
-                let (_off, seqno) = dec_try!(buf, off; decode_u16);
-                let seqno = u16::from_be(seqno);
+                let (off, id) = dec_try!(buf, off; decode_u16);
+                let (off, seqno) = dec_try!(buf, off; decode_u16);
                 icmp_header.set_options(ICMP6HeaderOptions::Type128 { id, seqno });
+                off
             }
             ICMP6Type::Type129 => {
-                let (_off, id) = dec_try!(buf, off; decode_u16);
-                let id = u16::from_be(id);
-                let (_off, seqno) = dec_try!(buf, off; decode_u16);
-                let seqno = u16::from_be(seqno);
+                let (off, id) = dec_try!(buf, off; decode_u16);
+                let (off, seqno) = dec_try!(buf, off; decode_u16);
                 icmp_header.set_options(ICMP6HeaderOptions::Type129 { id, seqno });
+                off
             }
-        }
+        };
 
         stream_done!(off, icmp_header);
     }
@@ -0,0 +1,307 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! This file contains an in-kernel ICMPv6 echo responder, which answers Echo
+//! Request messages (`ping6`) addressed to this node with an Echo Reply
+//! carrying the same identifier, sequence number and data.
+//!
+//! The [ICMP6EchoResponder](struct.ICMP6EchoResponder.html) is an
+//! [IP6RecvClient](../../ipv6/ipv6_recv/trait.IP6RecvClient.html), and
+//! replies through an [IP6Sender](../../ipv6/ipv6_send/trait.IP6Sender.html)
+//! that must not be shared with other senders, as the responder has to be
+//! its sole `IP6SendClient`. Any packet which is not an Echo Request for
+//! this node is passed on unmodified to the responder's own receive client
+//! (if set), so the responder can be placed in front of another receiver.
+//!
+//! Only one reply can be in flight at a time; requests arriving while a reply
+//! is being transmitted, or whose data does not fit in the reply buffer, are
+//! dropped.
+
+use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions, ICMP6Type};
+use crate::net::ipv6::ip_utils::{ip6_nh, IPAddr};
+use crate::net::ipv6::ipv6_recv::IP6RecvClient;
+use crate::net::ipv6::ipv6_send::{IP6SendClient, IP6Sender};
+use crate::net::ipv6::{IP6Header, TransportHeader};
+use crate::net::network_capabilities::NetworkCapability;
+
+use core::cell::Cell;
+
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::ErrorCode;
+
+/// A struct that replies to ICMPv6 Echo Requests destined to this node.
+pub struct ICMP6EchoResponder<'a, T: IP6Sender<'a>> {
+    ip_send_struct: &'a T,
+    /// Addresses of the local interfaces; requests to other unicast
+    /// addresses are not answered.
+    interface_list: &'a [IPAddr],
+    buffer: TakeCell<'static, [u8]>,
+    busy: Cell<bool>,
+    net_cap: &'static NetworkCapability,
+    client: OptionalCell<&'a dyn IP6RecvClient>,
+}
+
+impl<'a, T: IP6Sender<'a>> ICMP6EchoResponder<'a, T> {
+    pub fn new(
+        ip_send_struct: &'a T,
+        interface_list: &'a [IPAddr],
+        buffer: &'static mut [u8],
+        net_cap: &'static NetworkCapability,
+    ) -> ICMP6EchoResponder<'a, T> {
+        ICMP6EchoResponder {
+            ip_send_struct,
+            interface_list,
+            buffer: TakeCell::new(buffer),
+            busy: Cell::new(false),
+            net_cap,
+            client: OptionalCell::empty(),
+        }
+    }
+
+    /// Sets the client which receives all packets that are not answered by
+    /// the responder.
+    pub fn set_client(&self, client: &'a dyn IP6RecvClient) {
+        self.client.set(client);
+    }
+
+    /// Sends `reply`, echoing `data`.
+    fn reply(&self, reply: EchoReply, data: &[u8]) -> Result<(), ErrorCode> {
+        if self.busy.get() {
+            return Err(ErrorCode::BUSY);
+        }
+        let buffer = self.buffer.take().ok_or(ErrorCode::NOMEM)?;
+        if data.len() > buffer.len() {
+            self.buffer.replace(buffer);
+            return Err(ErrorCode::SIZE);
+        }
+        buffer[..data.len()].copy_from_slice(data);
+
+        let mut payload = SubSliceMut::new(buffer);
+        payload.slice(..data.len());
+
+        self.ip_send_struct.set_addr(reply.src);
+        // The IP sender copies the payload into its own packet buffer
+        // synchronously, so the reply buffer can be returned right away.
+        let result = self.ip_send_struct.send_to(
+            reply.dst,
+            TransportHeader::ICMP(reply.header),
+            &payload,
+            self.net_cap,
+        );
+        payload.reset();
+        self.buffer.replace(payload.take());
+        if result.is_ok() {
+            self.busy.set(true);
+        }
+        result
+    }
+}
+
+/// An Echo Reply answering a received Echo Request.
+struct EchoReply {
+    src: IPAddr,
+    dst: IPAddr,
+    header: ICMP6Header,
+    /// Offset of the data to echo in the ICMPv6 message of the request.
+    data_offset: usize,
+}
+
+/// Returns the reply to send if `payload` is an Echo Request addressed to one
+/// of `interface_list` or to a multicast group.
+fn echo_reply(header: &IP6Header, payload: &[u8], interface_list: &[IPAddr]) -> Option<EchoReply> {
+    let dst_addr = header.get_dst_addr();
+    let is_local = interface_list.contains(&dst_addr);
+    if header.get_next_header() != ip6_nh::ICMP || !(is_local || dst_addr.is_multicast()) {
+        return None;
+    }
+    let (data_offset, icmp_header) = ICMP6Header::decode(payload).done()?;
+    let (id, seqno) = match icmp_header.get_options() {
+        ICMP6HeaderOptions::Type128 { id, seqno } => (id, seqno),
+        _ => return None,
+    };
+    // Replies to multicast requests are sent from the first interface
+    // address.
+    let src = if is_local {
+        dst_addr
+    } else {
+        *interface_list.first()?
+    };
+
+    let mut reply_header = ICMP6Header::new(ICMP6Type::Type129);
+    reply_header.set_options(ICMP6HeaderOptions::Type129 { id, seqno });
+    // The checksum computation relies on the length being set
+    reply_header.set_len((payload.len() - data_offset + reply_header.get_hdr_size()) as u16);
+
+    Some(EchoReply {
+        src,
+        dst: header.get_src_addr(),
+        header: reply_header,
+        data_offset,
+    })
+}
+
+impl<'a, T: IP6Sender<'a>> IP6RecvClient for ICMP6EchoResponder<'a, T> {
+    fn receive(&self, header: IP6Header, payload: &[u8]) {
+        match echo_reply(&header, payload, self.interface_list) {
+            Some(reply) => {
+                let data_offset = reply.data_offset;
+                // Requests which cannot be answered right now are dropped,
+                // the sender will retransmit.
+                let _ = self.reply(reply, &payload[data_offset..]);
+            }
+            None => {
+                self.client.map(|client| client.receive(header, payload));
+            }
+        }
+    }
+}
+
+impl<'a, T: IP6Sender<'a>> IP6SendClient for ICMP6EchoResponder<'a, T> {
+    fn send_done(&self, _result: Result<(), ErrorCode>) {
+        self.busy.set(false);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::echo_reply;
+    use crate::net::icmpv6::{ICMP6Header, ICMP6HeaderOptions};
+    use crate::net::ipv6::ip_utils::{compute_sum, IPAddr};
+    use crate::net::ipv6::{IP6Header, IP6Packet, IPPayload, TransportHeader, ICMP_HDR_LEN};
+    use kernel::utilities::leasable_buffer::SubSliceMut;
+    use kernel::ErrorCode;
+    use std::boxed::Box;
+
+    // Echo Requests sent with `ping6`-style raw sockets to ::1 and the Echo
+    // Replies of the Linux kernel, captured on the loopback interface. The
+    // identifier is 0x1234 and the sequence number 7.
+    const REQUEST_13: &str = "600766ba00153a40000000000000000000000000000000010000000000000000\
+        00000000000000018000191412340007746f636b2070696e6736206f6b";
+    const REPLY_13: &str = "600dccaa00153a40000000000000000000000000000000010000000000000000\
+        00000000000000018100181412340007746f636b2070696e6736206f6b";
+    const REQUEST_20: &str = "600766ba001c3a40000000000000000000000000000000010000000000000000\
+        0000000000000001800082c7123400076576656e2d6c656e677468207061796c6f616421";
+    const REPLY_20: &str = "600dccaa001c3a40000000000000000000000000000000010000000000000000\
+        0000000000000001810081c7123400076576656e2d6c656e677468207061796c6f616421";
+
+    fn packet(hex: &str) -> ([u8; 128], usize) {
+        let mut buf = [0; 128];
+        let len = hex.len() / 2;
+        for (i, byte) in buf[..len].iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        (buf, len)
+    }
+
+    fn ip_header(packet: &[u8]) -> IP6Header {
+        IP6Header::decode(packet).done().unwrap().1
+    }
+
+    fn loopback() -> IPAddr {
+        let mut addr = IPAddr::new();
+        addr.0[15] = 1;
+        addr
+    }
+
+    #[test]
+    fn test_decode_echo() {
+        for (hex, data, request) in [
+            (REQUEST_13, &b"tock ping6 ok"[..], true),
+            (REPLY_13, &b"tock ping6 ok"[..], false),
+            (REQUEST_20, &b"even-length payload!"[..], true),
+            (REPLY_20, &b"even-length payload!"[..], false),
+        ] {
+            let (buf, len) = packet(hex);
+            let (offset, header) = ICMP6Header::decode(&buf[40..len]).done().unwrap();
+            assert_eq!(offset, ICMP_HDR_LEN);
+            assert_eq!(&buf[40 + offset..len], data);
+            match (header.get_options(), request) {
+                (ICMP6HeaderOptions::Type128 { id, seqno }, true)
+                | (ICMP6HeaderOptions::Type129 { id, seqno }, false) => {
+                    assert_eq!(id, 0x1234);
+                    assert_eq!(seqno, 7);
+                }
+                _ => panic!("wrong ICMPv6 type"),
+            }
+            assert_eq!(header.get_cksum(), u16::from_be_bytes([buf[42], buf[43]]));
+        }
+    }
+
+    #[test]
+    fn test_transport_checksum() {
+        for hex in [REQUEST_13, REPLY_13, REQUEST_20, REPLY_20] {
+            let (mut buf, len) = packet(hex);
+            let header = ip_header(&buf);
+            assert_eq!(header.check_transport_checksum(&buf[40..len]), Ok(()));
+
+            // Corrupting the last data byte must be detected.
+            buf[len - 1] ^= 0x01;
+            assert_eq!(
+                header.check_transport_checksum(&buf[40..len]),
+                Err(ErrorCode::FAIL)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sum_odd_length() {
+        // The trailing byte is padded with zero.
+        assert_eq!(compute_sum(&[0x12, 0x34, 0x56], 3), 0x1234 + 0x5600);
+    }
+
+    #[test]
+    fn test_echo_reply() {
+        for (request, expected) in [(REQUEST_13, REPLY_13), (REQUEST_20, REPLY_20)] {
+            let (buf, len) = packet(request);
+            let header = ip_header(&buf);
+            let reply = echo_reply(&header, &buf[40..len], &[loopback()]).unwrap();
+            assert_eq!(reply.data_offset, ICMP_HDR_LEN);
+
+            // Build the packet the way `IP6SendStruct` does.
+            let data = Box::leak(Box::new([0; 64]));
+            let data_len = len - 40 - reply.data_offset;
+            data[..data_len].copy_from_slice(&buf[40 + reply.data_offset..len]);
+            let mut payload = SubSliceMut::new(&mut data[..]);
+            payload.slice(..data_len);
+
+            let mut packet_data = [0; 64];
+            let mut ip6_packet = IP6Packet::new(IPPayload::new(
+                TransportHeader::ICMP(reply.header),
+                &mut packet_data,
+            ));
+            ip6_packet.header.src_addr = reply.src;
+            ip6_packet.header.dst_addr = reply.dst;
+            ip6_packet.set_payload(TransportHeader::ICMP(reply.header), &payload);
+            ip6_packet.set_transport_checksum();
+
+            let mut encoded = [0; 128];
+            let (encoded_len, _) = ip6_packet.encode(&mut encoded).done().unwrap();
+
+            let (mut expected, expected_len) = packet(expected);
+            assert_eq!(encoded_len, expected_len);
+            // Linux sets a flow label and a hop limit of 64.
+            expected[1..4].copy_from_slice(&encoded[1..4]);
+            expected[7] = encoded[7];
+            assert_eq!(&encoded[..encoded_len], &expected[..expected_len]);
+        }
+    }
+
+    #[test]
+    fn test_no_reply() {
+        let (buf, len) = packet(REQUEST_13);
+        let header = ip_header(&buf);
+        // Not addressed to this node.
+        let mut other = loopback();
+        other.0[15] = 2;
+        assert!(echo_reply(&header, &buf[40..len], &[other]).is_none());
+
+        // Not an Echo Request.
+        let (buf, len) = packet(REPLY_13);
+        let header = ip_header(&buf);
+        assert!(echo_reply(&header, &buf[40..len], &[loopback()]).is_none());
+    }
+}
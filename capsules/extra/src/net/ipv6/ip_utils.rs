@@ -219,7 +219,7 @@ pub fn compute_icmp_checksum(
     while sum > 0xffff {
         let sum_upper = sum >> 16;
         let sum_lower = sum & 0xffff;
-        sum += sum_upper + sum_lower;
+        sum = sum_upper + sum_lower;
     }
 
     sum = !sum;
@@ -245,7 +245,8 @@ pub fn compute_ipv6_ph_sum(ip6_header: &IP6Header) -> u32 {
         i += 2;
     }
 
-    sum += ip6_header.payload_len as u32;
+    // `payload_len` is stored in network byte order
+    sum += ip6_header.get_payload_len() as u32;
     sum += ip6_header.next_header as u32;
 
     sum
@@ -257,7 +258,12 @@ pub fn compute_sum(buf: &[u8], len: u16) -> u32 {
     let mut i: usize = 0;
     while i < (len as usize) {
         let msb = (buf[i] as u32) << 8;
-        let lsb = buf[i + 1] as u32;
+        // An odd trailing byte is padded with zero (RFC 1071)
+        let lsb = if i + 1 < len as usize {
+            buf[i + 1] as u32
+        } else {
+            0
+        };
         sum += msb + lsb;
         i += 2;
     }
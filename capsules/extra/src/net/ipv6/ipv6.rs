@@ -286,20 +286,22 @@ impl IP6Header {
                 Ok(())
             }
             ip6_nh::ICMP => {
-                // Untested (10/5/18)
-                let mut icmp_header: [u8; ICMP_HDR_LEN] = [0; ICMP_HDR_LEN];
-                icmp_header.copy_from_slice(&buf[..ICMP_HDR_LEN]);
-                let checksum = match ICMP6Header::decode(&icmp_header).done() {
+                if buf.len() < ICMP_HDR_LEN {
+                    return Err(ErrorCode::FAIL);
+                }
+                // Unlike the UDP checksum computation, `compute_icmp_checksum`
+                // skips the checksum field, so compare against it instead of 0.
+                match ICMP6Header::decode(&buf[..ICMP_HDR_LEN]).done() {
                     Some((_offset, mut hdr)) => {
                         hdr.set_len(buf.len() as u16);
-                        u16::from_be(compute_icmp_checksum(self, &hdr, &buf[ICMP_HDR_LEN..]))
+                        let checksum = compute_icmp_checksum(self, &hdr, &buf[ICMP_HDR_LEN..]);
+                        if checksum != hdr.get_cksum() {
+                            return Err(ErrorCode::FAIL); //Incorrect cksum
+                        }
+                        Ok(())
                     }
-                    None => 0xffff, //Will be dropped, as ones comp -0 checksum is invalid
-                };
-                if checksum != 0 {
-                    return Err(ErrorCode::FAIL); //Incorrect cksum
+                    None => Err(ErrorCode::FAIL),
                 }
-                Ok(())
             }
             _ => Err(ErrorCode::NOSUPPORT),
         }
@@ -163,8 +163,18 @@
 //
 // The RxState struct maintains the in-progress packet buffer, a bitmap
 // indicating which 8-byte chunks have not yet been received, the source/dest
-// mac address pair, datagram size and tag, and a start time (to lazily
-// expire timed-out reassembly processes).
+// mac address pair, datagram size and tag, and a start time (to expire
+// timed-out reassembly processes).
+//
+// Reassembly timeout and eviction:
+// A reassembly which does not complete within `FRAG_TIMEOUT` is aborted, so
+// that a lost fragment does not pin an RxState buffer. The Sixlowpan object
+// sets its alarm for the reassembly which times out next, and also expires
+// timed-out reassemblies whenever a new packet finds no free RxState. If all
+// RxStates are still busy after that, the configured `EvictionPolicy`
+// decides whether the new packet is dropped or the oldest reassembly is
+// aborted in its favor. Expired and evicted reassemblies as well as dropped
+// frames are counted in `Sixlowpan::reassembly_counters`.
 //
 // SixlowpanRxClient:
 // The SixlowpanRxClient trait has a single function, `receive`. Upper layers
@@ -243,13 +253,37 @@ use core::cmp::min;
 use kernel::collections::list::{List, ListLink, ListNode};
 use kernel::hil::radio;
 use kernel::hil::time;
-use kernel::hil::time::{Frequency, Ticks};
+use kernel::hil::time::{ConvertTicks, Ticks};
 use kernel::utilities::cells::{MapCell, TakeCell};
+use kernel::utilities::statistics::Counters;
 use kernel::ErrorCode;
 
 // Reassembly timeout in seconds
 const FRAG_TIMEOUT: u32 = 60;
 
+/// Counter slot for reassemblies which timed out before all fragments were
+/// received.
+pub const REASSEMBLIES_EXPIRED: usize = 0;
+/// Counter slot for reassemblies which were aborted to make room for a new
+/// packet (see [EvictionPolicy::EvictOldest]).
+pub const REASSEMBLIES_EVICTED: usize = 1;
+/// Counter slot for received frames which were dropped because no
+/// [RxState](struct.RxState.html) was free (see [EvictionPolicy::DropNew]).
+pub const FRAMES_DROPPED: usize = 2;
+/// Number of reassembly counters kept by [Sixlowpan](struct.Sixlowpan.html).
+pub const NUM_REASSEMBLY_COUNTERS: usize = 3;
+
+/// What [Sixlowpan](struct.Sixlowpan.html) does with a new packet when all
+/// [RxState](struct.RxState.html)s are busy reassembling other packets.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Drop the new packet and keep the reassemblies in progress.
+    DropNew,
+    /// Abort the reassembly which started first and receive the new packet
+    /// into its `RxState`.
+    EvictOldest,
+}
+
 /// Objects that implement this trait can set themselves to be the client
 /// for the [Sixlowpan](struct.Sixlowpan.html) struct, and will then receive
 /// a callback once an IPv6 packet has been fully reassembled.
@@ -639,7 +673,8 @@ pub struct RxState<'a> {
     // Marks if this instance is being used for a packet reassembly or if it is
     // free to use for a new packet.
     busy: Cell<bool>,
-    // The time when packet reassembly started for the current packet.
+    // The time when packet reassembly started for the current packet, as
+    // left-justified ticks.
     start_time: Cell<u32>,
 
     next: ListLink<'a, RxState<'a>>,
@@ -686,14 +721,15 @@ impl<'a> RxState<'a> {
             && (self.dst_mac_addr.get() == dst_mac_addr)
     }
 
-    // Checks if a given RxState is free or expired (and thus, can be freed).
-    // This function implements the reassembly timeout for 6LoWPAN lazily.
-    fn is_busy(&self, frequency: u32, current_time: u32) -> bool {
-        let expired = current_time >= (self.start_time.get() + FRAG_TIMEOUT * frequency);
-        if expired {
-            self.end_receive(None, Err(ErrorCode::FAIL));
-        }
-        self.busy.get()
+    // Returns the time since the reassembly started, in left-justified ticks.
+    fn elapsed(&self, current_time: u32) -> u32 {
+        current_time.wrapping_sub(self.start_time.get())
+    }
+
+    // Checks if a given RxState is busy with a reassembly which has not
+    // completed within `timeout` (and thus, can be freed).
+    fn is_expired(&self, timeout: u32, current_time: u32) -> bool {
+        self.busy.get() && self.elapsed(current_time) >= timeout
     }
 
     fn start_receive(
@@ -793,6 +829,11 @@ impl<'a> RxState<'a> {
 ///
 /// Finally, `set_client` controls the client that will receive transmission
 /// completion and reception callbacks.
+///
+/// Reassemblies which do not complete within 60 seconds are aborted when the
+/// alarm passed to `new` fires, so `Sixlowpan` must be set as the client of
+/// that alarm. `set_eviction_policy` controls what happens to new packets
+/// when all `RxState`s are busy.
 pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
     pub ctx_store: C,
     clock: &'a A,
@@ -801,6 +842,8 @@ pub struct Sixlowpan<'a, A: time::Alarm<'a>, C: ContextStore> {
 
     // Receive state
     rx_states: List<'a, RxState<'a>>,
+    eviction_policy: Cell<EvictionPolicy>,
+    reassembly_counters: Counters<NUM_REASSEMBLY_COUNTERS>,
 }
 
 // This function is called after receiving a frame
@@ -832,6 +875,12 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> RxClient for Sixlowpan<'a, A, C> {
     }
 }
 
+impl<'a, A: time::Alarm<'a>, C: ContextStore> time::AlarmClient for Sixlowpan<'a, A, C> {
+    fn alarm(&self) {
+        self.expire_reassemblies();
+    }
+}
+
 impl<'a, A: time::Alarm<'a>, C: ContextStore> SixlowpanState<'a> for Sixlowpan<'a, A, C> {
     fn next_dgram_tag(&self) -> u16 {
         // Increment dgram_tag
@@ -875,8 +924,9 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
     /// frame.
     ///
     /// * `clock` - A implementation of `Alarm` used for tracking the timing of
-    /// frame arrival. The clock should be continue running during sleep and
-    /// have an accuracy of at least 60 seconds.
+    /// frame arrival and for expiring timed-out reassemblies. The clock should
+    /// be continue running during sleep and have an accuracy of at least 60
+    /// seconds. It must not be shared with other alarm clients.
     pub fn new(ctx_store: C, clock: &'a A) -> Sixlowpan<'a, A, C> {
         Sixlowpan {
             ctx_store: ctx_store,
@@ -885,6 +935,110 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             rx_client: Cell::new(None),
 
             rx_states: List::new(),
+            eviction_policy: Cell::new(EvictionPolicy::DropNew),
+            reassembly_counters: Counters::new(),
+        }
+    }
+
+    /// Sets what happens to new packets when all `RxState`s are busy. The
+    /// default is [EvictionPolicy::DropNew].
+    pub fn set_eviction_policy(&self, policy: EvictionPolicy) {
+        self.eviction_policy.set(policy);
+    }
+
+    /// Returns the counters of expired and evicted reassemblies and dropped
+    /// frames, indexed by `REASSEMBLIES_EXPIRED`, `REASSEMBLIES_EVICTED` and
+    /// `FRAMES_DROPPED`.
+    pub fn reassembly_counters(&self) -> &Counters<NUM_REASSEMBLY_COUNTERS> {
+        &self.reassembly_counters
+    }
+
+    // Returns the reassembly timeout in left-justified ticks.
+    fn reassembly_timeout(&self) -> u32 {
+        self.clock
+            .ticks_from_seconds(FRAG_TIMEOUT)
+            .into_u32_left_justified()
+    }
+
+    // Aborts all reassemblies which have not completed within `FRAG_TIMEOUT`,
+    // and sets the alarm for the reassembly which times out next.
+    fn expire_reassemblies(&self) {
+        let now = self.clock.now();
+        let current_time = now.into_u32_left_justified();
+        let timeout = self.reassembly_timeout();
+        let mut next_expiry: Option<u32> = None;
+        for state in self.rx_states.iter().filter(|state| state.busy.get()) {
+            if state.is_expired(timeout, current_time) {
+                state.end_receive(None, Err(ErrorCode::FAIL));
+                self.reassembly_counters.increment(REASSEMBLIES_EXPIRED);
+            } else {
+                let remaining = timeout - state.elapsed(current_time);
+                next_expiry = Some(next_expiry.map_or(remaining, |next| min(next, remaining)));
+            }
+        }
+        match next_expiry {
+            Some(remaining) => self
+                .clock
+                .set_alarm(now, A::Ticks::from(remaining >> A::Ticks::u32_padding())),
+            None => {
+                let _ = self.clock.disarm();
+            }
+        }
+    }
+
+    // Starts a reassembly in `state`, setting the alarm for its timeout
+    // unless an earlier one is already pending.
+    fn start_reassembly(
+        &self,
+        state: &RxState<'a>,
+        src_mac_addr: MacAddress,
+        dst_mac_addr: MacAddress,
+        dgram_size: u16,
+        dgram_tag: u16,
+    ) {
+        let now = self.clock.now();
+        state.start_receive(
+            src_mac_addr,
+            dst_mac_addr,
+            dgram_size,
+            dgram_tag,
+            now.into_u32_left_justified(),
+        );
+        if !self.clock.is_armed() {
+            self.clock
+                .set_alarm(now, self.clock.ticks_from_seconds(FRAG_TIMEOUT));
+        }
+    }
+
+    // Returns a free `RxState` for a new packet. If all `RxState`s are busy,
+    // timed-out reassemblies are expired first, and then the eviction policy
+    // is applied.
+    fn free_rx_state(&self) -> Option<&RxState<'a>> {
+        let find_free = || self.rx_states.iter().find(|state| !state.busy.get());
+        let rx_state = find_free().or_else(|| {
+            self.expire_reassemblies();
+            find_free()
+        });
+        if rx_state.is_some() {
+            return rx_state;
+        }
+        match self.eviction_policy.get() {
+            EvictionPolicy::DropNew => {
+                self.reassembly_counters.increment(FRAMES_DROPPED);
+                None
+            }
+            EvictionPolicy::EvictOldest => {
+                let current_time = self.clock.now().into_u32_left_justified();
+                let oldest = self
+                    .rx_states
+                    .iter()
+                    .max_by_key(|state| state.elapsed(current_time));
+                oldest.map(|state| {
+                    state.end_receive(None, Err(ErrorCode::NOMEM));
+                    self.reassembly_counters.increment(REASSEMBLIES_EVICTED);
+                    state
+                })
+            }
         }
     }
 
@@ -923,28 +1077,27 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         src_mac_addr: MacAddress,
         dst_mac_addr: MacAddress,
     ) -> (Option<&RxState<'a>>, Result<(), ErrorCode>) {
-        let rx_state = self
-            .rx_states
-            .iter()
-            .find(|state| !state.is_busy(self.clock.now().into_u32(), A::Frequency::frequency()));
+        // Filter non 6LoWPAN packets and return
+        if !is_lowpan(payload) {
+            return (None, Ok(()));
+        }
+
+        let rx_state = self.free_rx_state();
         rx_state.map_or((None, Err(ErrorCode::NOMEM)), |state| {
+            // A single packet is complete once decompressed, so it does not
+            // need a reassembly timeout.
             state.start_receive(
                 src_mac_addr,
                 dst_mac_addr,
                 payload_len as u16,
                 0,
-                self.clock.now().into_u32(),
+                self.clock.now().into_u32_left_justified(),
             );
             // The packet buffer should *always* be there; in particular,
             // since this state is not busy, it must have the packet buffer.
             // Otherwise, we are in an inconsistent state and can fail.
             let packet = state.packet.take().unwrap();
 
-            // Filter non 6LoWPAN packets and return
-            if !is_lowpan(payload) {
-                return (None, Ok(()));
-            }
-
             let decompressed = sixlowpan_compression::decompress(
                 &self.ctx_store,
                 &payload[0..payload_len],
@@ -963,6 +1116,8 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
                     state.dgram_size.set((written + remaining) as u16);
                 }
                 Err(()) => {
+                    state.packet.replace(packet);
+                    state.end_receive(None, Err(ErrorCode::FAIL));
                     return (None, Err(ErrorCode::FAIL));
                 }
             }
@@ -991,20 +1146,21 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
             .iter()
             .find(|state| state.is_my_fragment(src_mac_addr, dst_mac_addr, dgram_size, dgram_tag));
 
+        // A fragment arriving for a timed-out reassembly starts a new one
+        let current_time = self.clock.now().into_u32_left_justified();
+        if rx_state.map_or(false, |state| {
+            state.is_expired(self.reassembly_timeout(), current_time)
+        }) {
+            self.expire_reassemblies();
+            rx_state = None;
+        }
+
         // Else find a free state
         if rx_state.is_none() {
-            rx_state = self.rx_states.iter().find(|state| {
-                !state.is_busy(self.clock.now().into_u32(), A::Frequency::frequency())
-            });
+            rx_state = self.free_rx_state();
             // Initialize new state
             rx_state.map(|state| {
-                state.start_receive(
-                    src_mac_addr,
-                    dst_mac_addr,
-                    dgram_size,
-                    dgram_tag,
-                    self.clock.now().into_u32(),
-                )
+                self.start_reassembly(state, src_mac_addr, dst_mac_addr, dgram_size, dgram_tag)
             });
             if rx_state.is_none() {
                 return (None, Err(ErrorCode::NOMEM));
@@ -1047,3 +1203,239 @@ impl<'a, A: time::Alarm<'a>, C: ContextStore> Sixlowpan<'a, A, C> {
         // TODO: Need to get buffer back from Mac layer on disassociation
     }
 }
+
+#[cfg(test)]
+mod test {
+    extern crate std;
+
+    use super::*;
+    use kernel::hil::time::{Alarm, AlarmClient, Freq1KHz, Ticks24, Ticks32, Time};
+    use kernel::utilities::cells::OptionalCell;
+    use std::boxed::Box;
+
+    const SRC_MAC_ADDR: MacAddress = MacAddress::Short(0x1234);
+    const DST_MAC_ADDR: MacAddress = MacAddress::Short(0x5678);
+    const DGRAM_SIZE: u16 = 300;
+
+    /// Reassembly timeout of `FakeAlarm` in ticks.
+    const TIMEOUT_TICKS: u32 = FRAG_TIMEOUT * 1000;
+
+    /// An alarm whose counter only advances when the test advances it.
+    struct FakeAlarm<'a, T: Ticks> {
+        now: Cell<T>,
+        alarm: Cell<T>,
+        armed: Cell<bool>,
+        client: OptionalCell<&'a dyn AlarmClient>,
+    }
+
+    impl<T: Ticks> FakeAlarm<'_, T> {
+        fn new(now: u32) -> Self {
+            Self {
+                now: Cell::new(now.into()),
+                alarm: Cell::new(0.into()),
+                armed: Cell::new(false),
+                client: OptionalCell::empty(),
+            }
+        }
+
+        /// Advances time by `ticks`, firing the alarm if it expires.
+        fn advance(&self, ticks: u32) {
+            let start = self.now.get();
+            self.now.set(start.wrapping_add(ticks.into()));
+            let expired = self.alarm.get().wrapping_sub(start).into_u32() <= ticks;
+            if self.armed.get() && expired {
+                self.armed.set(false);
+                self.client.map(|client| client.alarm());
+            }
+        }
+    }
+
+    impl<T: Ticks> Time for FakeAlarm<'_, T> {
+        type Ticks = T;
+        type Frequency = Freq1KHz;
+
+        fn now(&self) -> T {
+            self.now.get()
+        }
+    }
+
+    impl<'a, T: Ticks> Alarm<'a> for FakeAlarm<'a, T> {
+        fn set_alarm_client(&self, client: &'a dyn AlarmClient) {
+            self.client.set(client);
+        }
+
+        fn set_alarm(&self, reference: T, dt: T) {
+            self.alarm.set(reference.wrapping_add(dt));
+            self.armed.set(true);
+        }
+
+        fn get_alarm(&self) -> T {
+            self.alarm.get()
+        }
+
+        fn disarm(&self) -> Result<(), ErrorCode> {
+            self.armed.set(false);
+            Ok(())
+        }
+
+        fn is_armed(&self) -> bool {
+            self.armed.get()
+        }
+
+        fn minimum_dt(&self) -> T {
+            0.into()
+        }
+    }
+
+    type TestSixlowpan<T> =
+        Sixlowpan<'static, FakeAlarm<'static, T>, sixlowpan_compression::Context>;
+
+    /// Creates a `Sixlowpan` with `rx_states` `RxState`s, which is the client
+    /// of its alarm if `alarm_client` is set.
+    fn sixlowpan<T: Ticks>(
+        now: u32,
+        rx_states: usize,
+        alarm_client: bool,
+    ) -> (&'static FakeAlarm<'static, T>, &'static TestSixlowpan<T>) {
+        let alarm: &'static FakeAlarm<T> = Box::leak(Box::new(FakeAlarm::new(now)));
+        let ctx_store = sixlowpan_compression::Context {
+            prefix: [0; 16],
+            prefix_len: 0,
+            id: 0,
+            compress: false,
+        };
+        let sixlowpan: &'static TestSixlowpan<T> =
+            Box::leak(Box::new(Sixlowpan::new(ctx_store, alarm)));
+        for _ in 0..rx_states {
+            let buffer = Box::leak(Box::new([0; 1280]));
+            sixlowpan.add_rx_state(Box::leak(Box::new(RxState::new(buffer))));
+        }
+        if alarm_client {
+            alarm.set_alarm_client(sixlowpan);
+        }
+        (alarm, sixlowpan)
+    }
+
+    /// Receives one of the later 64-byte fragments of the datagram
+    /// `dgram_tag`, and returns whether a reassembly buffer was available for
+    /// it.
+    fn receive_fragment_at<T: Ticks>(
+        sixlowpan: &TestSixlowpan<T>,
+        dgram_tag: u16,
+        dgram_offset: usize,
+    ) -> bool {
+        let mut frame = [0; lowpan_frag::FRAGN_HDR_SIZE + 64];
+        set_frag_hdr(DGRAM_SIZE, dgram_tag, dgram_offset, &mut frame, false);
+        let (rx_state, result) =
+            sixlowpan.receive_frame(&frame, frame.len(), SRC_MAC_ADDR, DST_MAC_ADDR);
+        assert!(rx_state.is_none());
+        result.is_ok()
+    }
+
+    fn receive_fragment<T: Ticks>(sixlowpan: &TestSixlowpan<T>, dgram_tag: u16) -> bool {
+        receive_fragment_at(sixlowpan, dgram_tag, 64)
+    }
+
+    fn reassembling<T: Ticks>(sixlowpan: &TestSixlowpan<T>, dgram_tag: u16) -> bool {
+        sixlowpan
+            .rx_states
+            .iter()
+            .any(|state| state.is_my_fragment(SRC_MAC_ADDR, DST_MAC_ADDR, DGRAM_SIZE, dgram_tag))
+    }
+
+    #[test]
+    fn test_reassembly_expires() {
+        let (alarm, sixlowpan) = sixlowpan::<Ticks32>(1000, 2, true);
+        assert!(receive_fragment(sixlowpan, 1));
+        assert!(alarm.is_armed());
+
+        alarm.advance(TIMEOUT_TICKS / 2);
+        assert!(receive_fragment(sixlowpan, 2));
+        alarm.advance(TIMEOUT_TICKS / 2 - 1);
+        assert!(reassembling(sixlowpan, 1));
+
+        // Each reassembly times out on its own
+        alarm.advance(1);
+        assert!(!reassembling(sixlowpan, 1));
+        assert!(reassembling(sixlowpan, 2));
+        assert!(alarm.is_armed());
+
+        alarm.advance(TIMEOUT_TICKS / 2);
+        assert!(!reassembling(sixlowpan, 2));
+        assert!(!alarm.is_armed());
+        let counters = sixlowpan.reassembly_counters();
+        assert_eq!(counters.snapshot(), [2, 0, 0]);
+    }
+
+    #[test]
+    fn test_reassembly_expires_across_wraparound() {
+        let (alarm, sixlowpan) = sixlowpan::<Ticks24>(0xFF_FF00, 1, true);
+        assert!(receive_fragment(sixlowpan, 1));
+
+        alarm.advance(TIMEOUT_TICKS - 1);
+        assert!(reassembling(sixlowpan, 1));
+        alarm.advance(1);
+        assert!(!reassembling(sixlowpan, 1));
+        assert_eq!(
+            sixlowpan.reassembly_counters().get(REASSEMBLIES_EXPIRED),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_expired_reassembly_freed_without_alarm() {
+        let (alarm, sixlowpan) = sixlowpan::<Ticks32>(0, 1, false);
+        assert!(receive_fragment(sixlowpan, 1));
+        alarm.advance(TIMEOUT_TICKS);
+
+        // A new packet finds the timed-out reassembly and replaces it
+        assert!(receive_fragment(sixlowpan, 2));
+        assert!(!reassembling(sixlowpan, 1));
+        assert!(reassembling(sixlowpan, 2));
+
+        // A late fragment of a timed-out packet starts a new reassembly
+        alarm.advance(TIMEOUT_TICKS);
+        assert!(receive_fragment(sixlowpan, 2));
+        assert!(reassembling(sixlowpan, 2));
+        assert_eq!(sixlowpan.reassembly_counters().snapshot(), [2, 0, 0]);
+    }
+
+    #[test]
+    fn test_drop_new() {
+        let (alarm, sixlowpan) = sixlowpan::<Ticks32>(0, 1, true);
+        assert!(receive_fragment(sixlowpan, 1));
+        alarm.advance(TIMEOUT_TICKS - 1);
+
+        assert!(!receive_fragment(sixlowpan, 2));
+        assert!(!receive_fragment(sixlowpan, 3));
+        assert!(reassembling(sixlowpan, 1));
+        assert_eq!(sixlowpan.reassembly_counters().snapshot(), [0, 0, 2]);
+    }
+
+    #[test]
+    fn test_evict_oldest() {
+        let (alarm, sixlowpan) = sixlowpan::<Ticks32>(0, 2, true);
+        sixlowpan.set_eviction_policy(EvictionPolicy::EvictOldest);
+        assert!(receive_fragment(sixlowpan, 1));
+        alarm.advance(10);
+        assert!(receive_fragment(sixlowpan, 2));
+        alarm.advance(10);
+
+        assert!(receive_fragment(sixlowpan, 3));
+        assert!(!reassembling(sixlowpan, 1));
+        assert!(reassembling(sixlowpan, 2));
+        assert!(reassembling(sixlowpan, 3));
+
+        // Fragments of reassemblies in progress do not evict anything
+        assert!(receive_fragment_at(sixlowpan, 2, 128));
+        assert!(reassembling(sixlowpan, 3));
+        assert_eq!(sixlowpan.reassembly_counters().snapshot(), [0, 1, 0]);
+
+        // The evicted reassembly's timeout does not affect its replacement
+        alarm.advance(TIMEOUT_TICKS - 20);
+        assert!(reassembling(sixlowpan, 3));
+        alarm.advance(20);
+        assert!(!reassembling(sixlowpan, 3));
+        assert_eq!(sixlowpan.reassembly_counters().snapshot(), [2, 1, 0]);
+    }
+}
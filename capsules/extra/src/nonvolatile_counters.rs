@@ -0,0 +1,316 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Counters which persist across reboots in nonvolatile storage.
+//!
+//! This keeps a boot counter, which is incremented on every call to
+//! [`NonvolatileCounters::init`], and a frame counter, such as the outgoing
+//! 802.15.4 security frame counter, which has to survive reboots so that
+//! frame counter values are never reused.
+//!
+//! The counters are stored as a small record in one of two slots of the
+//! underlying storage, which are written alternately. Each record carries a
+//! sequence number and a checksum, so the most recent valid record is used
+//! even if the device loses power while a record is being written. Placing
+//! the two slots in different flash pages spreads the wear over both pages.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let nv_counters = components::nonvolatile_counters::NonvolatileCountersComponent::new(
+//!     nv_to_pages,
+//!     0x3E000, // start of the first slot
+//!     0x1000,  // slot size, one flash page
+//! )
+//! .finalize(components::nonvolatile_counters_component_static!(
+//!     capsules_extra::nonvolatile_to_pages::NonvolatileToPages<'static, F>
+//! ));
+//! nv_counters.set_client(client);
+//! nv_counters.init();
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::nonvolatile_storage::{NonvolatileStorage, NonvolatileStorageClient};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Length of a stored record, and the buffer length required by the capsule.
+pub const RECORD_LEN: usize = 20;
+
+/// Marks a slot as holding a record.
+const RECORD_MAGIC: u32 = 0x4E564354; // "NVCT"
+
+/// Client of [`NonvolatileCounters`].
+pub trait NonvolatileCountersClient {
+    /// Called when [`NonvolatileCounters::init`] has finished, with the new
+    /// boot count.
+    fn init_done(&self, result: Result<u32, ErrorCode>);
+
+    /// Called when a new frame counter value passed to
+    /// [`NonvolatileCounters::set_frame_counter`] has been stored.
+    fn frame_counter_stored(&self, result: Result<(), ErrorCode>);
+}
+
+#[derive(Clone, Copy, Default)]
+struct Record {
+    sequence: u32,
+    boot_count: u32,
+    frame_counter: u32,
+}
+
+impl Record {
+    fn checksum(&self) -> u32 {
+        !(RECORD_MAGIC
+            ^ self.sequence
+            ^ self.boot_count.rotate_left(8)
+            ^ self.frame_counter.rotate_left(16))
+    }
+
+    fn encode(&self, buf: &mut [u8]) {
+        buf[0..4].copy_from_slice(&RECORD_MAGIC.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.boot_count.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.frame_counter.to_le_bytes());
+        buf[16..20].copy_from_slice(&self.checksum().to_le_bytes());
+    }
+
+    /// Returns the record stored in `buf`, if it holds a valid one.
+    fn decode(buf: &[u8]) -> Option<Record> {
+        let word = |i: usize| u32::from_le_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        if word(0) != RECORD_MAGIC {
+            return None;
+        }
+        let record = Record {
+            sequence: word(4),
+            boot_count: word(8),
+            frame_counter: word(12),
+        };
+        if word(16) == record.checksum() {
+            Some(record)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this record was written after `other`, allowing for the
+    /// sequence number to wrap around.
+    fn is_newer_than(&self, other: &Record) -> bool {
+        (self.sequence.wrapping_sub(other.sequence) as i32) > 0
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum State {
+    /// `init` has not been called, or has failed.
+    Uninitialized,
+    ReadFirstSlot,
+    ReadSecondSlot,
+    /// Writing the record with the incremented boot counter.
+    WriteBootCount,
+    Idle,
+    /// Writing the record with a new frame counter.
+    WriteFrameCounter,
+}
+
+pub struct NonvolatileCounters<'a, S: NonvolatileStorage<'a>> {
+    storage: &'a S,
+    /// Address of the first slot. The second slot follows `slot_size` bytes
+    /// later.
+    start_address: usize,
+    slot_size: usize,
+    buffer: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a dyn NonvolatileCountersClient>,
+    state: Cell<State>,
+    /// Record read from the first slot during `init`.
+    first_record: OptionalCell<Record>,
+    /// The last record which has been stored, and its slot.
+    current: Cell<Record>,
+    current_slot: Cell<usize>,
+    /// The record being written.
+    pending: Cell<Record>,
+}
+
+impl<'a, S: NonvolatileStorage<'a>> NonvolatileCounters<'a, S> {
+    pub fn new(
+        storage: &'a S,
+        start_address: usize,
+        slot_size: usize,
+        buffer: &'static mut [u8],
+    ) -> NonvolatileCounters<'a, S> {
+        NonvolatileCounters {
+            storage,
+            start_address,
+            slot_size,
+            buffer: TakeCell::new(buffer),
+            client: OptionalCell::empty(),
+            state: Cell::new(State::Uninitialized),
+            first_record: OptionalCell::empty(),
+            current: Cell::new(Record::default()),
+            // So that the first record is written to the first slot
+            current_slot: Cell::new(1),
+            pending: Cell::new(Record::default()),
+        }
+    }
+
+    pub fn set_client(&self, client: &'a dyn NonvolatileCountersClient) {
+        self.client.set(client);
+    }
+
+    /// Loads the counters from storage and increments the boot counter.
+    ///
+    /// This should be called once at boot. The result is reported through
+    /// [`NonvolatileCountersClient::init_done`].
+    pub fn init(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Uninitialized {
+            return Err(ErrorCode::ALREADY);
+        }
+        self.read_slot(0)?;
+        self.state.set(State::ReadFirstSlot);
+        Ok(())
+    }
+
+    /// Returns the boot count, which is 1 on the first boot.
+    pub fn boot_count(&self) -> u32 {
+        self.current.get().boot_count
+    }
+
+    /// Returns the last stored frame counter.
+    pub fn frame_counter(&self) -> u32 {
+        self.current.get().frame_counter
+    }
+
+    /// Stores a new frame counter value.
+    ///
+    /// Callers should store a value ahead of the frame counter in use, so
+    /// that it does not need to be stored for every frame. The frame counter
+    /// can not go backwards, so values lower than the stored one are rejected
+    /// with `INVAL`. The result is reported through
+    /// [`NonvolatileCountersClient::frame_counter_stored`].
+    pub fn set_frame_counter(&self, frame_counter: u32) -> Result<(), ErrorCode> {
+        match self.state.get() {
+            State::Idle => {}
+            State::Uninitialized => return Err(ErrorCode::OFF),
+            _ => return Err(ErrorCode::BUSY),
+        }
+        let mut record = self.current.get();
+        if frame_counter < record.frame_counter {
+            return Err(ErrorCode::INVAL);
+        }
+        record.frame_counter = frame_counter;
+        self.write_record(record)?;
+        self.state.set(State::WriteFrameCounter);
+        Ok(())
+    }
+
+    fn slot_address(&self, slot: usize) -> usize {
+        self.start_address + slot * self.slot_size
+    }
+
+    fn read_slot(&self, slot: usize) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        self.storage
+            .read(buffer, self.slot_address(slot), RECORD_LEN)
+    }
+
+    /// Writes `record` with the next sequence number into the slot which does
+    /// not hold the current record.
+    fn write_record(&self, mut record: Record) -> Result<(), ErrorCode> {
+        let buffer = self.buffer.take().ok_or(ErrorCode::BUSY)?;
+        record.sequence = self.current.get().sequence.wrapping_add(1);
+        record.encode(buffer);
+        self.pending.set(record);
+        self.storage.write(
+            buffer,
+            self.slot_address(1 - self.current_slot.get()),
+            RECORD_LEN,
+        )
+    }
+
+    /// Picks the most recent valid record after both slots have been read,
+    /// and stores it with an incremented boot counter.
+    fn records_loaded(&self, second: Option<Record>) {
+        let first = self.first_record.take();
+        let newest = match (first, second) {
+            (Some(first), Some(second)) if second.is_newer_than(&first) => Some((second, 1)),
+            (Some(first), _) => Some((first, 0)),
+            (None, Some(second)) => Some((second, 1)),
+            (None, None) => None,
+        };
+        if let Some((record, slot)) = newest {
+            self.current.set(record);
+            self.current_slot.set(slot);
+        }
+
+        let mut record = self.current.get();
+        record.boot_count = record.boot_count.wrapping_add(1);
+        match self.write_record(record) {
+            Ok(()) => self.state.set(State::WriteBootCount),
+            Err(e) => self.init_failed(e),
+        }
+    }
+
+    fn init_failed(&self, error: ErrorCode) {
+        self.state.set(State::Uninitialized);
+        self.client.map(|client| client.init_done(Err(error)));
+    }
+}
+
+impl<'a, S: NonvolatileStorage<'a>> NonvolatileStorageClient for NonvolatileCounters<'a, S> {
+    fn read_done(&self, buffer: &'static mut [u8], length: usize) {
+        let record = if length == RECORD_LEN {
+            Record::decode(buffer)
+        } else {
+            None
+        };
+        self.buffer.replace(buffer);
+
+        match self.state.get() {
+            State::ReadFirstSlot => {
+                if let Some(record) = record {
+                    self.first_record.set(record);
+                }
+                match self.read_slot(1) {
+                    Ok(()) => self.state.set(State::ReadSecondSlot),
+                    Err(e) => self.init_failed(e),
+                }
+            }
+            State::ReadSecondSlot => self.records_loaded(record),
+            _ => {}
+        }
+    }
+
+    fn write_done(&self, buffer: &'static mut [u8], length: usize) {
+        self.buffer.replace(buffer);
+
+        let result = if length == RECORD_LEN {
+            self.current.set(self.pending.get());
+            self.current_slot.set(1 - self.current_slot.get());
+            Ok(())
+        } else {
+            Err(ErrorCode::FAIL)
+        };
+
+        match self.state.get() {
+            State::WriteBootCount => {
+                // Without a stored boot counter, counting boots and handing
+                // out frame counters would not be reliable.
+                match result {
+                    Ok(()) => {
+                        self.state.set(State::Idle);
+                        let boot_count = self.boot_count();
+                        self.client.map(|client| client.init_done(Ok(boot_count)));
+                    }
+                    Err(e) => self.init_failed(e),
+                }
+            }
+            State::WriteFrameCounter => {
+                self.state.set(State::Idle);
+                self.client
+                    .map(|client| client.frame_counter_stored(result));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,284 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Driver for the TI OPT3001 ambient light sensor, implementing the
+//! `AmbientLight` HIL.
+//!
+//! <https://www.ti.com/lit/ds/symlink/opt3001.pdf>
+//!
+//! > The OPT3001 is a sensor that measures the intensity of visible light.
+//! > The spectral response of the sensor tightly matches the photopic response
+//! > of the human eye and includes significant infrared rejection.
+//!
+//! Each reading triggers a single-shot conversion with automatic full-scale
+//! range selection, after which the sensor returns to shutdown.
+//!
+//! If the INT pin is connected, the sensor is put into end-of-conversion
+//! mode and the driver waits for INT to be asserted before reading the
+//! result. Otherwise, the driver waits for the conversion time with an alarm
+//! and then polls the conversion ready flag. The alarm is also armed when the
+//! INT pin is used, with some margin, so that a missed edge only delays the
+//! reading instead of stalling the driver.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let opt3001 = components::opt3001::Opt3001Component::new(
+//!     mux_i2c,
+//!     capsules_extra::opt3001::BASE_ADDR,
+//!     mux_alarm,
+//!     Some(int_pin),
+//! )
+//! .finalize(components::opt3001_component_static!(
+//!     nrf52::rtc::Rtc<'static>,
+//!     nrf52840::i2c::TWI
+//! ));
+//! let ambient_light = components::isl29035::AmbientLightComponent::new(
+//!     board_kernel,
+//!     capsules_extra::ambient_light::DRIVER_NUM,
+//!     opt3001,
+//! )
+//! .finalize(components::ambient_light_component_static!());
+//! ```
+
+use core::cell::Cell;
+use kernel::hil::gpio;
+use kernel::hil::i2c::{Error, I2CClient, I2CDevice};
+use kernel::hil::sensors::{AmbientLight, AmbientLightClient};
+use kernel::hil::time::{self, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// I2C address with the ADDR pin connected to GND.
+pub static BASE_ADDR: u8 = 0x44;
+
+/// Recommended buffer length.
+pub const BUF_LEN: usize = 3;
+
+enum Registers {
+    Result = 0x00,
+    Configuration = 0x01,
+    LowLimit = 0x02,
+}
+
+/// Configuration register value: automatic full-scale range, 100 ms
+/// conversion time, single-shot mode, latched interrupt reporting.
+const CONFIG_SINGLE_SHOT: u16 = 0b1100 << 12 | 0b01 << 9 | 1 << 4;
+
+/// Conversion ready flag in the configuration register.
+const CONFIG_CRF: u16 = 1 << 7;
+
+/// Low-limit register value which puts the INT pin into end-of-conversion
+/// mode, so that it is asserted (low) whenever a conversion has finished.
+const LOW_LIMIT_END_OF_CONVERSION: u16 = 0b1100 << 12;
+
+/// Maximum time of a 100 ms conversion.
+const CONVERSION_TIME_MS: u32 = 110;
+
+/// Time to wait before polling again if the conversion was not yet done.
+const POLL_INTERVAL_MS: u32 = 10;
+
+/// Extra time to wait for the INT pin before polling anyway.
+const INTERRUPT_TIMEOUT_MARGIN_MS: u32 = 20;
+
+#[derive(Copy, Clone, PartialEq)]
+enum State {
+    Idle,
+    /// Writing the low-limit register to enable end-of-conversion interrupts.
+    EnablingInterrupt,
+    /// Writing the configuration register to start a conversion.
+    Starting,
+    /// Waiting for the conversion to finish, signalled by either the alarm
+    /// or the INT pin.
+    Converting,
+    /// Reading the configuration register to check the conversion ready flag.
+    Polling,
+    /// Reading the result register.
+    Reading,
+}
+
+pub struct Opt3001<'a, A: time::Alarm<'a>, I: I2CDevice> {
+    i2c: &'a I,
+    alarm: &'a A,
+    interrupt_pin: Option<&'a dyn gpio::InterruptPin<'a>>,
+    /// Whether the INT pin has been put into end-of-conversion mode.
+    interrupt_enabled: Cell<bool>,
+    state: Cell<State>,
+    buffer: TakeCell<'static, [u8]>,
+    client: OptionalCell<&'a dyn AmbientLightClient>,
+}
+
+impl<'a, A: time::Alarm<'a>, I: I2CDevice> Opt3001<'a, A, I> {
+    pub fn new(
+        i2c: &'a I,
+        alarm: &'a A,
+        interrupt_pin: Option<&'a dyn gpio::InterruptPin<'a>>,
+        buffer: &'static mut [u8],
+    ) -> Opt3001<'a, A, I> {
+        Opt3001 {
+            i2c,
+            alarm,
+            interrupt_pin,
+            interrupt_enabled: Cell::new(false),
+            state: Cell::new(State::Idle),
+            buffer: TakeCell::new(buffer),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn start_read_lux(&self) -> Result<(), ErrorCode> {
+        if self.state.get() != State::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        self.buffer.take().map_or(Err(ErrorCode::NOMEM), |buf| {
+            self.i2c.enable();
+            // The low-limit register only needs to be written once
+            let (register, value, state) =
+                if self.interrupt_pin.is_some() && !self.interrupt_enabled.get() {
+                    (
+                        Registers::LowLimit,
+                        LOW_LIMIT_END_OF_CONVERSION,
+                        State::EnablingInterrupt,
+                    )
+                } else {
+                    (
+                        Registers::Configuration,
+                        CONFIG_SINGLE_SHOT,
+                        State::Starting,
+                    )
+                };
+            buf[0] = register as u8;
+            buf[1..3].copy_from_slice(&value.to_be_bytes());
+
+            if let Err((error, buf)) = self.i2c.write(buf, 3) {
+                self.buffer.replace(buf);
+                self.i2c.disable();
+                Err(error.into())
+            } else {
+                self.state.set(state);
+                Ok(())
+            }
+        })
+    }
+
+    /// Waits for the conversion to finish. This waits for `ms` milliseconds
+    /// before polling. If the INT pin is connected, the alarm is only a
+    /// timeout in case the edge is missed, and whichever of the two fires
+    /// first polls the sensor.
+    fn wait_for_conversion(&self, buffer: &'static mut [u8], ms: u32) {
+        self.buffer.replace(buffer);
+        self.state.set(State::Converting);
+        let ms = if self.interrupt_pin.is_some() {
+            ms + INTERRUPT_TIMEOUT_MARGIN_MS
+        } else {
+            ms
+        };
+        let interval = self.alarm.ticks_from_ms(ms);
+        self.alarm.set_alarm(self.alarm.now(), interval);
+    }
+
+    /// Reads the configuration register to check whether the conversion has
+    /// finished.
+    fn poll_conversion(&self) {
+        if self.state.get() != State::Converting {
+            return;
+        }
+        let _ = self.alarm.disarm();
+        if let Some(buffer) = self.buffer.take() {
+            self.read_register(buffer, Registers::Configuration, State::Polling);
+        }
+    }
+
+    /// Reads a 16 bit register, moving to `state` once the read is issued.
+    fn read_register(&self, buffer: &'static mut [u8], register: Registers, state: State) {
+        buffer[0] = register as u8;
+        if let Err((_error, buffer)) = self.i2c.write_read(buffer, 1, 2) {
+            self.finish(buffer, 0);
+        } else {
+            self.state.set(state);
+        }
+    }
+
+    fn finish(&self, buffer: &'static mut [u8], lux: usize) {
+        let _ = self.alarm.disarm();
+        self.buffer.replace(buffer);
+        self.i2c.disable();
+        self.state.set(State::Idle);
+        self.client.map(|client| client.callback(lux));
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, I: I2CDevice> AmbientLight<'a> for Opt3001<'a, A, I> {
+    fn set_client(&self, client: &'a dyn AmbientLightClient) {
+        self.client.set(client)
+    }
+
+    fn read_light_intensity(&self) -> Result<(), ErrorCode> {
+        self.start_read_lux()
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, I: I2CDevice> time::AlarmClient for Opt3001<'a, A, I> {
+    fn alarm(&self) {
+        self.poll_conversion();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, I: I2CDevice> gpio::Client for Opt3001<'a, A, I> {
+    fn fired(&self) {
+        // Reading the configuration register also clears the latched INT pin
+        self.poll_conversion();
+    }
+}
+
+impl<'a, A: time::Alarm<'a>, I: I2CDevice> I2CClient for Opt3001<'a, A, I> {
+    fn command_complete(&self, buffer: &'static mut [u8], status: Result<(), Error>) {
+        if status.is_err() {
+            self.finish(buffer, 0);
+            return;
+        }
+        match self.state.get() {
+            State::EnablingInterrupt => {
+                self.interrupt_enabled.set(true);
+                if let Some(pin) = self.interrupt_pin {
+                    pin.make_input();
+                    pin.enable_interrupts(gpio::InterruptEdge::FallingEdge);
+                }
+
+                buffer[0] = Registers::Configuration as u8;
+                buffer[1..3].copy_from_slice(&CONFIG_SINGLE_SHOT.to_be_bytes());
+                if let Err((_error, buffer)) = self.i2c.write(buffer, 3) {
+                    self.finish(buffer, 0);
+                } else {
+                    self.state.set(State::Starting);
+                }
+            }
+            State::Starting => {
+                self.wait_for_conversion(buffer, CONVERSION_TIME_MS);
+            }
+            State::Polling => {
+                let config = u16::from_be_bytes([buffer[0], buffer[1]]);
+                if config & CONFIG_CRF != 0 {
+                    self.read_register(buffer, Registers::Result, State::Reading);
+                } else {
+                    self.wait_for_conversion(buffer, POLL_INTERVAL_MS);
+                }
+            }
+            State::Reading => {
+                // lux = 0.01 * 2^E * R, with the exponent E in the top four
+                // bits and the mantissa R in the lower twelve bits.
+                let result = u16::from_be_bytes([buffer[0], buffer[1]]);
+                let exponent = (result >> 12) as usize;
+                let mantissa = (result & 0x0FFF) as usize;
+                let lux = (mantissa << exponent) / 100;
+
+                self.finish(buffer, lux);
+            }
+            State::Idle | State::Converting => {
+                self.buffer.replace(buffer);
+            }
+        }
+    }
+}
@@ -0,0 +1,445 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Batches sensor records from processes and kernel drivers into the log
+//! storage and/or UDP uploads.
+//!
+//! Sensor readings are exchanged as fixed-size records of [`RECORD_LEN`]
+//! bytes, all fields little endian:
+//!
+//! | Offset | Size | Field                                  |
+//! |--------|------|----------------------------------------|
+//! | 0      | 4    | Timestamp (`u32`, board-defined units) |
+//! | 4      | 4    | Value (`i32`, sensor-defined units)    |
+//! | 8      | 2    | Sensor id (`u16`)                      |
+//! | 10     | 2    | Flags (`u16`)                          |
+//!
+//! Records are collected into a batch of up to [`BATCH_RECORDS`] records.
+//! When the batch is full, or when a flush is requested, it is appended as one
+//! entry to the log (if one is configured) and then sent as one UDP datagram
+//! (if an upload destination is configured). Two buffers are used, so records
+//! can be accepted while the previous batch is being flushed. If both buffers
+//! are in use, appends are refused with `BUSY` and the process is notified
+//! with an upcall once the flush has completed.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let sensor_records = static_init!(
+//!     capsules_extra::sensor_records::SensorRecords<'static>,
+//!     capsules_extra::sensor_records::SensorRecords::new(
+//!         Some(log),
+//!         Some(udp_upload),
+//!         batch_buffer,
+//!         flush_buffer,
+//!         board_kernel.create_grant(
+//!             capsules_extra::sensor_records::DRIVER_NUM,
+//!             &memory_allocation_capability
+//!         ),
+//!     )
+//! );
+//! log.set_append_client(sensor_records);
+//! udp_send.set_client(sensor_records);
+//! ```
+
+use core::cell::Cell;
+
+use kernel::grant::{AllowRoCount, AllowRwCount, Grant, UpcallCount};
+use kernel::hil::log::{LogWrite, LogWriteClient};
+use kernel::processbuffer::{ReadableProcessBuffer, ReadableProcessSlice};
+use kernel::syscall::{CommandReturn, SyscallDriver};
+use kernel::utilities::cells::TakeCell;
+use kernel::utilities::leasable_buffer::SubSliceMut;
+use kernel::{ErrorCode, ProcessId};
+
+use crate::net::ipv6::ip_utils::IPAddr;
+use crate::net::network_capabilities::NetworkCapability;
+use crate::net::udp::udp_send::{UDPSendClient, UDPSender};
+
+/// Syscall driver number.
+use capsules_core::driver;
+pub const DRIVER_NUM: usize = driver::NUM::SensorRecords as usize;
+
+/// Size of one encoded record in bytes.
+pub const RECORD_LEN: usize = 12;
+/// Number of records in one batch.
+pub const BATCH_RECORDS: usize = 16;
+/// Size of the batch buffers in bytes.
+pub const BATCH_LEN: usize = RECORD_LEN * BATCH_RECORDS;
+
+/// Ids for read-only allow buffers
+mod ro_allow {
+    /// Records to append
+    pub const RECORDS: usize = 0;
+    /// The number of allow buffers the kernel stores for this grant
+    pub const COUNT: u8 = 1;
+}
+
+/// A single sensor reading.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Record {
+    pub timestamp: u32,
+    pub value: i32,
+    pub sensor_id: u16,
+    pub flags: u16,
+}
+
+impl Record {
+    /// Writes the record into the first [`RECORD_LEN`] bytes of `buf`.
+    pub fn encode(&self, buf: &mut [u8]) -> Result<(), ErrorCode> {
+        if buf.len() < RECORD_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        buf[0..4].copy_from_slice(&self.timestamp.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.value.to_le_bytes());
+        buf[8..10].copy_from_slice(&self.sensor_id.to_le_bytes());
+        buf[10..12].copy_from_slice(&self.flags.to_le_bytes());
+        Ok(())
+    }
+
+    /// Reads a record from the first [`RECORD_LEN`] bytes of `buf`.
+    pub fn decode(buf: &[u8]) -> Result<Record, ErrorCode> {
+        if buf.len() < RECORD_LEN {
+            return Err(ErrorCode::SIZE);
+        }
+        Ok(Record {
+            timestamp: u32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]]),
+            value: i32::from_le_bytes([buf[4], buf[5], buf[6], buf[7]]),
+            sensor_id: u16::from_le_bytes([buf[8], buf[9]]),
+            flags: u16::from_le_bytes([buf[10], buf[11]]),
+        })
+    }
+}
+
+/// Destination of the UDP uploads.
+pub struct UdpUpload<'a> {
+    sender: &'a dyn UDPSender<'a>,
+    dest: IPAddr,
+    dst_port: u16,
+    net_cap: &'static NetworkCapability,
+}
+
+impl<'a> UdpUpload<'a> {
+    /// `sender` must already be bound to a local port.
+    pub fn new(
+        sender: &'a dyn UDPSender<'a>,
+        dest: IPAddr,
+        dst_port: u16,
+        net_cap: &'static NetworkCapability,
+    ) -> UdpUpload<'a> {
+        UdpUpload {
+            sender,
+            dest,
+            dst_port,
+            net_cap,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FlushState {
+    Idle,
+    Logging,
+    Uploading,
+}
+
+/// Per-process state.
+#[derive(Default)]
+pub struct App {
+    // The process is waiting for the end of a flush, either because it asked
+    // for one or because its records were refused.
+    waiting: bool,
+}
+
+pub struct SensorRecords<'a> {
+    log: Option<&'a dyn LogWrite<'a>>,
+    udp: Option<UdpUpload<'a>>,
+    /// Buffer that records are appended to.
+    batch: TakeCell<'static, [u8]>,
+    batch_len: Cell<usize>,
+    /// Second buffer, which is lent to the log and UDP layers while a batch
+    /// is being flushed.
+    flush_buffer: TakeCell<'static, [u8]>,
+    flush_len: Cell<usize>,
+    flush_state: Cell<FlushState>,
+    // First error seen while flushing the current batch.
+    flush_result: Cell<Result<(), ErrorCode>>,
+    grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+}
+
+impl<'a> SensorRecords<'a> {
+    /// Both buffers must be [`BATCH_LEN`] bytes long.
+    pub fn new(
+        log: Option<&'a dyn LogWrite<'a>>,
+        udp: Option<UdpUpload<'a>>,
+        batch: &'static mut [u8],
+        flush_buffer: &'static mut [u8],
+        grant: Grant<App, UpcallCount<1>, AllowRoCount<{ ro_allow::COUNT }>, AllowRwCount<0>>,
+    ) -> SensorRecords<'a> {
+        SensorRecords {
+            log,
+            udp,
+            batch: TakeCell::new(batch),
+            batch_len: Cell::new(0),
+            flush_buffer: TakeCell::new(flush_buffer),
+            flush_len: Cell::new(0),
+            flush_state: Cell::new(FlushState::Idle),
+            flush_result: Cell::new(Ok(())),
+            grant,
+        }
+    }
+
+    /// Adds a record from a kernel driver to the batch.
+    ///
+    /// Returns `BUSY` if the batch is full and the previous batch is still
+    /// being flushed; the record is not stored in that case.
+    pub fn push(&self, record: Record) -> Result<(), ErrorCode> {
+        if self.batch_full() {
+            return Err(ErrorCode::BUSY);
+        }
+        let offset = self.batch_len.get();
+        self.batch.map_or(Err(ErrorCode::NOMEM), |batch| {
+            record.encode(&mut batch[offset..offset + RECORD_LEN])
+        })?;
+        self.batch_len.set(offset + RECORD_LEN);
+        if self.batch_full() {
+            // A failure here means a flush is already running; the batch is
+            // flushed when it completes.
+            let _ = self.flush();
+        }
+        Ok(())
+    }
+
+    /// Starts flushing the records collected so far.
+    ///
+    /// Returns `BUSY` if a flush is already in progress and `ALREADY` if
+    /// there are no records to flush.
+    pub fn flush(&self) -> Result<(), ErrorCode> {
+        if self.flush_state.get() != FlushState::Idle {
+            return Err(ErrorCode::BUSY);
+        }
+        let len = self.batch_len.get();
+        if len == 0 {
+            return Err(ErrorCode::ALREADY);
+        }
+        let spare = self.flush_buffer.take().ok_or(ErrorCode::NOMEM)?;
+        let buffer = match self.batch.replace(spare) {
+            Some(buffer) => buffer,
+            None => return Err(ErrorCode::NOMEM),
+        };
+        self.batch_len.set(0);
+        self.flush_len.set(len);
+        self.flush_result.set(Ok(()));
+
+        match self.log {
+            Some(log) => match log.append(buffer, len) {
+                Ok(()) => self.flush_state.set(FlushState::Logging),
+                Err((e, buffer)) => {
+                    self.flush_result.set(Err(e));
+                    self.upload(buffer);
+                }
+            },
+            None => self.upload(buffer),
+        }
+        Ok(())
+    }
+
+    fn batch_full(&self) -> bool {
+        self.batch_len.get() + RECORD_LEN > BATCH_LEN
+    }
+
+    fn upload(&self, buffer: &'static mut [u8]) {
+        match self.udp {
+            Some(ref udp) => {
+                let mut dgram = SubSliceMut::new(buffer);
+                dgram.slice(..self.flush_len.get());
+                match udp
+                    .sender
+                    .send_to(udp.dest, udp.dst_port, dgram, udp.net_cap)
+                {
+                    Ok(()) => self.flush_state.set(FlushState::Uploading),
+                    Err(mut dgram) => {
+                        dgram.reset();
+                        self.flush_done(dgram.take(), Err(ErrorCode::FAIL));
+                    }
+                }
+            }
+            None => self.flush_done(buffer, Ok(())),
+        }
+    }
+
+    fn flush_done(&self, buffer: &'static mut [u8], result: Result<(), ErrorCode>) {
+        if result.is_err() && self.flush_result.get().is_ok() {
+            self.flush_result.set(result);
+        }
+        self.flush_buffer.replace(buffer);
+        self.flush_state.set(FlushState::Idle);
+
+        let records = self.flush_len.get() / RECORD_LEN;
+        let status = kernel::errorcode::into_statuscode(self.flush_result.get());
+        for process in self.grant.iter() {
+            process.enter(|app, kernel_data| {
+                if app.waiting {
+                    app.waiting = false;
+                    kernel_data.schedule_upcall(0, (status, records, 0)).ok();
+                }
+            });
+        }
+
+        // Records may have filled the other buffer in the meantime.
+        if self.batch_full() {
+            let _ = self.flush();
+        }
+    }
+
+    /// Copies as many whole records from `records` into the batch as fit and
+    /// returns how many were copied.
+    fn copy_records(&self, records: &ReadableProcessSlice) -> usize {
+        let offset = self.batch_len.get();
+        let count = core::cmp::min(records.len(), BATCH_LEN - offset) / RECORD_LEN;
+        let len = count * RECORD_LEN;
+        self.batch.map_or(0, |batch| {
+            records[..len].copy_to_slice(&mut batch[offset..offset + len]);
+            self.batch_len.set(offset + len);
+            count
+        })
+    }
+
+    /// Appends `count` records from the process's allow buffer, flushing
+    /// full batches along the way. Returns how many records were accepted.
+    fn append(&self, processid: ProcessId, count: usize) -> Result<usize, ErrorCode> {
+        let len = count.checked_mul(RECORD_LEN).ok_or(ErrorCode::INVAL)?;
+        let mut accepted = 0;
+        loop {
+            let copied = self
+                .grant
+                .enter(processid, |_, kernel_data| {
+                    kernel_data
+                        .get_readonly_processbuffer(ro_allow::RECORDS)
+                        .and_then(|records| {
+                            records.enter(|records| match records.get(accepted * RECORD_LEN..len) {
+                                Some(records) => Ok(self.copy_records(records)),
+                                None => Err(ErrorCode::SIZE),
+                            })
+                        })
+                        .unwrap_or(Err(ErrorCode::RESERVE))
+                })
+                .unwrap_or_else(|err| Err(err.into()))?;
+            accepted += copied;
+
+            // Flush outside of the grant, as completing a flush synchronously
+            // notifies all waiting processes.
+            if accepted < count && self.batch_full() && self.flush().is_ok() {
+                continue;
+            }
+            break;
+        }
+
+        if self.batch_full() {
+            let _ = self.flush();
+        }
+        if accepted < count {
+            self.grant
+                .enter(processid, |app, _| app.waiting = true)
+                .map_err(ErrorCode::from)?;
+            if accepted == 0 {
+                return Err(ErrorCode::BUSY);
+            }
+        }
+        Ok(accepted)
+    }
+}
+
+impl LogWriteClient for SensorRecords<'_> {
+    fn append_done(
+        &self,
+        buffer: &'static mut [u8],
+        _length: usize,
+        _records_lost: bool,
+        error: Result<(), ErrorCode>,
+    ) {
+        if let Err(e) = error {
+            self.flush_result.set(Err(e));
+        }
+        self.upload(buffer);
+    }
+
+    fn sync_done(&self, _error: Result<(), ErrorCode>) {}
+
+    fn erase_done(&self, _error: Result<(), ErrorCode>) {}
+}
+
+impl UDPSendClient for SensorRecords<'_> {
+    fn send_done(&self, result: Result<(), ErrorCode>, mut dgram: SubSliceMut<'static, u8>) {
+        dgram.reset();
+        self.flush_done(dgram.take(), result);
+    }
+}
+
+/// Processes append encoded records to the batch with the `allow` and
+/// `command` system calls.
+impl SyscallDriver for SensorRecords<'_> {
+    /// Command interface.
+    ///
+    /// ### `command_num`
+    ///
+    /// - `0`: Driver existence check.
+    /// - `1`: Append the first `arg1` records of the read-only allow buffer
+    ///   `0`. Returns the number of records accepted, which can be fewer
+    ///   than requested if the batch buffers are full; returns `BUSY` if no
+    ///   record was accepted. In both cases the process receives an upcall
+    ///   once the current flush completes. Returns `SIZE` if the allow buffer
+    ///   holds fewer than `arg1` records.
+    /// - `2`: Flush the records collected so far. The process receives an
+    ///   upcall once the flush completes. Returns `ALREADY` if there are no
+    ///   records to flush and `BUSY` if a flush is already in progress.
+    ///
+    /// ### Upcall `0`
+    ///
+    /// Signals the end of a flush, with the status of the flush and the
+    /// number of records in the flushed batch.
+    fn command(
+        &self,
+        command_num: usize,
+        arg1: usize,
+        _: usize,
+        processid: ProcessId,
+    ) -> CommandReturn {
+        match command_num {
+            0 => CommandReturn::success(),
+
+            1 => match self.append(processid, arg1) {
+                Ok(accepted) => CommandReturn::success_u32(accepted as u32),
+                Err(e) => CommandReturn::failure(e),
+            },
+
+            2 => {
+                // Mark the process first, as the flush may complete
+                // synchronously.
+                let was_waiting = match self.grant.enter(processid, |app, _| {
+                    core::mem::replace(&mut app.waiting, true)
+                }) {
+                    Ok(was_waiting) => was_waiting,
+                    Err(err) => return CommandReturn::failure(err.into()),
+                };
+                match self.flush() {
+                    Ok(()) => CommandReturn::success(),
+                    Err(e) => {
+                        let _ = self
+                            .grant
+                            .enter(processid, |app, _| app.waiting = was_waiting);
+                        CommandReturn::failure(e)
+                    }
+                }
+            }
+
+            _ => CommandReturn::failure(ErrorCode::NOSUPPORT),
+        }
+    }
+
+    fn allocate_grant(&self, processid: ProcessId) -> Result<(), kernel::process::Error> {
+        self.grant.enter(processid, |_, _| {})
+    }
+}
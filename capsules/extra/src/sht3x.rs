@@ -54,7 +54,7 @@ enum State {
     ReadData,
 }
 
-fn crc8(data: &[u8]) -> u8 {
+pub(crate) fn crc8(data: &[u8]) -> u8 {
     let polynomial = 0x31;
     let mut crc = 0xff;
 
@@ -0,0 +1,355 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Test that an 802.15.4 radio follows the contract of the radio HIL.
+//!
+//! The test runs a fixed sequence of steps against any `hil::radio::Radio`
+//! implementation and checks, for each step:
+//!
+//! - `initialize()` does not turn the radio on,
+//! - `config_commit()` results in exactly one `config_done()` callback, after
+//!   which the getters return the committed values,
+//! - `start()` and `stop()` result in exactly one power client callback, and
+//!   `is_on()` matches the notified state,
+//! - a successful `transmit()` results in exactly one `send_done()` callback
+//!   which returns the same buffer,
+//! - a failing `transmit()` (frame too long, radio off) returns the buffer
+//!   with the documented error and no callback,
+//! - no other callbacks happen during the step.
+//!
+//! Each step waits [`STEP_WINDOW_MS`] before checking, so that late or
+//! duplicate callbacks are counted. Violations are printed over the console,
+//! and the test reports `IncorrectResult` to its client if there were any.
+//!
+//! The radio must not have been initialized or started by the board, and the
+//! test must be the radio's only client.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust,ignore
+//! let test = static_init!(
+//!     TestRadioConformance<'static, nrf52840::ieee802154_radio::Radio, VirtualMuxAlarm<'static, Rtc>>,
+//!     TestRadioConformance::new(radio, test_alarm, tx_buffer, rx_buffer)
+//! );
+//! test_alarm.set_alarm_client(test);
+//! radio.set_transmit_client(test);
+//! radio.set_receive_client(test);
+//! radio.set_config_client(test);
+//! radio.set_power_client(test);
+//! test.run();
+//! ```
+
+use core::cell::Cell;
+
+use capsules_core::test::capsule_test::{CapsuleTest, CapsuleTestClient, CapsuleTestError};
+use kernel::debug;
+use kernel::hil::radio::{self, Radio, RadioChannel};
+use kernel::hil::time::{Alarm, AlarmClient, ConvertTicks};
+use kernel::utilities::cells::{OptionalCell, TakeCell};
+use kernel::ErrorCode;
+
+/// Time to wait for callbacks in each step.
+pub const STEP_WINDOW_MS: u32 = 500;
+
+const PAN: u16 = 0xABCD;
+const ADDRESS: u16 = 0x1234;
+const ADDRESS_LONG: [u8; 8] = [0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x12, 0x34];
+const CHANNEL: RadioChannel = RadioChannel::Channel26;
+const TX_POWER: i8 = 0;
+
+/// Data frame from `ADDRESS` to the broadcast address, with a 4 byte
+/// payload.
+const FRAME: [u8; 13] = [
+    0x41, 0x88, // Frame control: data, PAN ID compression, short addresses
+    0x00, // Sequence number
+    0xCD, 0xAB, // Destination PAN
+    0xFF, 0xFF, // Destination address
+    0x34, 0x12, // Source address
+    b't', b'o', b'c', b'k',
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Step {
+    Initialize,
+    PowerOn,
+    Config,
+    Transmit,
+    TransmitTooLong,
+    PowerOff,
+    TransmitWhileOff,
+}
+
+impl Step {
+    fn next(self) -> Option<Step> {
+        match self {
+            Step::Initialize => Some(Step::PowerOn),
+            Step::PowerOn => Some(Step::Config),
+            Step::Config => Some(Step::Transmit),
+            Step::Transmit => Some(Step::TransmitTooLong),
+            Step::TransmitTooLong => Some(Step::PowerOff),
+            Step::PowerOff => Some(Step::TransmitWhileOff),
+            Step::TransmitWhileOff => None,
+        }
+    }
+
+    /// Number of `config_done`, power on, power off and `send_done`
+    /// callbacks expected during the step.
+    fn expected_callbacks(self) -> [usize; 4] {
+        match self {
+            Step::Config => [1, 0, 0, 0],
+            Step::PowerOn => [0, 1, 0, 0],
+            Step::PowerOff => [0, 0, 1, 0],
+            Step::Transmit => [0, 0, 0, 1],
+            Step::Initialize | Step::TransmitTooLong | Step::TransmitWhileOff => [0, 0, 0, 0],
+        }
+    }
+}
+
+pub struct TestRadioConformance<'a, R: Radio<'a>, A: Alarm<'a>> {
+    radio: &'a R,
+    alarm: &'a A,
+    step: Cell<Step>,
+    tx_buffer: TakeCell<'static, [u8]>,
+    /// Address of `tx_buffer`, to check that the radio returns it.
+    tx_buffer_addr: Cell<usize>,
+    rx_buffer: TakeCell<'static, [u8]>,
+    /// Callbacks seen during the current step, in the order of
+    /// `Step::expected_callbacks`.
+    callbacks: [Cell<usize>; 4],
+    config_result: Cell<Result<(), ErrorCode>>,
+    violations: Cell<usize>,
+    client: OptionalCell<&'static dyn CapsuleTestClient>,
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> TestRadioConformance<'a, R, A> {
+    /// `tx_buffer` and `rx_buffer` must be `radio::MAX_BUF_SIZE` bytes long.
+    pub fn new(
+        radio: &'a R,
+        alarm: &'a A,
+        tx_buffer: &'static mut [u8],
+        rx_buffer: &'static mut [u8],
+    ) -> Self {
+        TestRadioConformance {
+            radio,
+            alarm,
+            step: Cell::new(Step::Initialize),
+            tx_buffer_addr: Cell::new(tx_buffer.as_ptr() as usize),
+            tx_buffer: TakeCell::new(tx_buffer),
+            rx_buffer: TakeCell::new(rx_buffer),
+            callbacks: [const { Cell::new(0) }; 4],
+            config_result: Cell::new(Ok(())),
+            violations: Cell::new(0),
+            client: OptionalCell::empty(),
+        }
+    }
+
+    pub fn run(&self) {
+        if let Some(buf) = self.rx_buffer.take() {
+            self.radio.set_receive_buffer(buf);
+        }
+        self.violations.set(0);
+        self.start_step(Step::Initialize);
+    }
+
+    fn check(&self, ok: bool, what: &str) {
+        if !ok {
+            self.violations.set(self.violations.get() + 1);
+            debug!("RadioConformance FAIL ({:?}): {}", self.step.get(), what);
+        }
+    }
+
+    fn start_step(&self, step: Step) {
+        self.step.set(step);
+        for count in self.callbacks.iter() {
+            count.set(0);
+        }
+
+        match step {
+            Step::Initialize => {
+                self.check(self.radio.initialize().is_ok(), "initialize() failed");
+                self.check(!self.radio.is_on(), "initialize() turned the radio on");
+            }
+            Step::PowerOn => {
+                self.check(self.radio.start().is_ok(), "start() failed");
+            }
+            Step::Config => {
+                self.radio.set_pan(PAN);
+                self.radio.set_address(ADDRESS);
+                self.radio.set_address_long(ADDRESS_LONG);
+                self.radio.set_channel(CHANNEL);
+                self.check(
+                    self.radio.set_tx_power(TX_POWER).is_ok(),
+                    "set_tx_power() failed",
+                );
+                self.radio.config_commit();
+            }
+            Step::Transmit => self.transmit(FRAME.len(), None),
+            Step::TransmitTooLong => {
+                // Leaves no room for the MFR.
+                self.transmit(radio::MAX_BUF_SIZE, Some(ErrorCode::SIZE))
+            }
+            Step::PowerOff => {
+                self.check(self.radio.stop().is_ok(), "stop() failed");
+            }
+            Step::TransmitWhileOff => self.transmit(FRAME.len(), Some(ErrorCode::OFF)),
+        }
+
+        let window = self.alarm.ticks_from_ms(STEP_WINDOW_MS);
+        self.alarm.set_alarm(self.alarm.now(), window);
+    }
+
+    /// Transmits `FRAME` with the given frame length, and checks that the
+    /// call fails with `error` if given.
+    fn transmit(&self, frame_len: usize, error: Option<ErrorCode>) {
+        let buf = match self.tx_buffer.take() {
+            Some(buf) => buf,
+            None => {
+                self.check(false, "transmit buffer was not returned");
+                return;
+            }
+        };
+        buf[radio::PSDU_OFFSET..radio::PSDU_OFFSET + FRAME.len()].copy_from_slice(&FRAME);
+
+        match (self.radio.transmit(buf, frame_len), error) {
+            (Ok(()), None) => {}
+            (Ok(()), Some(_)) => self.check(false, "transmit() did not fail"),
+            (Err((_, buf)), None) => {
+                self.check(false, "transmit() failed");
+                self.return_tx_buffer(buf);
+            }
+            (Err((code, buf)), Some(expected)) => {
+                self.check(code == expected, "transmit() failed with the wrong error");
+                self.return_tx_buffer(buf);
+            }
+        }
+    }
+
+    fn return_tx_buffer(&self, buf: &'static mut [u8]) {
+        self.check(
+            buf.as_ptr() as usize == self.tx_buffer_addr.get(),
+            "a different transmit buffer was returned",
+        );
+        self.tx_buffer.replace(buf);
+    }
+
+    fn finish_step(&self) {
+        let step = self.step.get();
+        let expected = step.expected_callbacks();
+        let names = ["config_done()", "power on", "power off", "send_done()"];
+        for ((count, expected), name) in self.callbacks.iter().zip(expected).zip(names) {
+            if count.get() != expected {
+                self.violations.set(self.violations.get() + 1);
+                debug!(
+                    "RadioConformance FAIL ({:?}): {} callbacks: {}, expected {}",
+                    step,
+                    name,
+                    count.get(),
+                    expected
+                );
+            }
+        }
+
+        match step {
+            Step::PowerOn => self.check(self.radio.is_on(), "is_on() is false after start()"),
+            Step::Config => {
+                self.check(self.config_result.get().is_ok(), "config_done() failed");
+                self.check(self.radio.get_pan() == PAN, "PAN ID not committed");
+                self.check(
+                    self.radio.get_address() == ADDRESS,
+                    "short address not committed",
+                );
+                self.check(
+                    self.radio.get_address_long() == ADDRESS_LONG,
+                    "long address not committed",
+                );
+                self.check(
+                    self.radio.get_channel() == CHANNEL.get_channel_number(),
+                    "channel not committed",
+                );
+                self.check(
+                    self.radio.get_tx_power() == TX_POWER,
+                    "transmit power not committed",
+                );
+            }
+            Step::Transmit => {
+                self.check(self.tx_buffer.is_some(), "transmit buffer was not returned")
+            }
+            Step::PowerOff => self.check(!self.radio.is_on(), "is_on() is true after stop()"),
+            _ => {}
+        }
+
+        match step.next() {
+            Some(next) => self.start_step(next),
+            None => {
+                let violations = self.violations.get();
+                debug!("RadioConformance: finished, {} violations", violations);
+                self.client.map(|client| {
+                    client.done(if violations == 0 {
+                        Ok(())
+                    } else {
+                        Err(CapsuleTestError::IncorrectResult)
+                    })
+                });
+            }
+        }
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> CapsuleTest for TestRadioConformance<'a, R, A> {
+    fn set_client(&self, client: &'static dyn CapsuleTestClient) {
+        self.client.set(client);
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> AlarmClient for TestRadioConformance<'a, R, A> {
+    fn alarm(&self) {
+        self.finish_step();
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> radio::ConfigClient for TestRadioConformance<'a, R, A> {
+    fn config_done(&self, result: Result<(), ErrorCode>) {
+        self.callbacks[0].set(self.callbacks[0].get() + 1);
+        self.config_result.set(result);
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> radio::PowerClient for TestRadioConformance<'a, R, A> {
+    fn changed(&self, on: bool) {
+        let index = if on { 1 } else { 2 };
+        self.callbacks[index].set(self.callbacks[index].get() + 1);
+        self.check(
+            self.radio.is_on() == on,
+            "is_on() does not match the power notification",
+        );
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> radio::TxClient for TestRadioConformance<'a, R, A> {
+    fn send_done(&self, buf: &'static mut [u8], _acked: bool, _result: Result<(), ErrorCode>) {
+        // The transmission itself may fail, e.g. with BUSY if the channel is
+        // never clear, but the buffer must be returned.
+        self.callbacks[3].set(self.callbacks[3].get() + 1);
+        self.return_tx_buffer(buf);
+    }
+}
+
+impl<'a, R: Radio<'a>, A: Alarm<'a>> radio::RxClient for TestRadioConformance<'a, R, A> {
+    fn receive(
+        &self,
+        buf: &'static mut [u8],
+        frame_len: usize,
+        _lqi: u8,
+        _crc_valid: bool,
+        _result: Result<(), ErrorCode>,
+    ) {
+        // Frames from other devices may be received at any time.
+        self.check(
+            radio::PSDU_OFFSET + frame_len <= buf.len(),
+            "received frame length exceeds the receive buffer",
+        );
+        self.radio.set_receive_buffer(buf);
+    }
+}
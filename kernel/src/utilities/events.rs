@@ -0,0 +1,290 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fixed-size ring of typed kernel events.
+//!
+//! [`EventRing`] lets subsystems report conditions such as a radio reset or
+//! a receive overflow to a single consumer, e.g. the process console or a
+//! capsule reporting over the network, instead of printing them with
+//! `debug!` where they happen. Events are stored in atomics, so they can be
+//! published from an interrupt handler and consumed from the kernel loop
+//! without a critical section.
+//!
+//! The ring holds the last `N` events. When it is full, publishing an event
+//! overwrites the oldest one, and the number of overwritten events is
+//! counted.
+//!
+//! On targets without atomic read-modify-write instructions (e.g. ARMv6-M or
+//! RV32 without the A extension), updates are plain loads and stores. Events
+//! can only be published safely there if all publishers run outside of
+//! interrupt handlers.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! use kernel::utilities::events::{Event, EventKind, EventRing};
+//!
+//! static EVENTS: EventRing<8> = EventRing::new();
+//!
+//! EVENTS.publish(Event::new(EventKind::RxOverflow, 3));
+//! assert_eq!(EVENTS.pop(), Some(Event::new(EventKind::RxOverflow, 3)));
+//! assert_eq!(EVENTS.pop(), None);
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Kind of a kernel event.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum EventKind {
+    /// The radio was reset after an error.
+    RadioReset = 1,
+    /// Received data was dropped because no buffer was available.
+    RxOverflow = 2,
+    /// A flash write was deferred, e.g. because the flash was busy.
+    FlashWriteDeferred = 3,
+    /// The supply voltage dropped close to the brownout threshold.
+    BrownoutWarning = 4,
+    /// The watchdog is about to expire.
+    WatchdogNearExpiry = 5,
+}
+
+impl EventKind {
+    fn from_u8(value: u8) -> Option<EventKind> {
+        match value {
+            1 => Some(EventKind::RadioReset),
+            2 => Some(EventKind::RxOverflow),
+            3 => Some(EventKind::FlashWriteDeferred),
+            4 => Some(EventKind::BrownoutWarning),
+            5 => Some(EventKind::WatchdogNearExpiry),
+            _ => None,
+        }
+    }
+}
+
+/// A kernel event, consisting of its kind and an event-specific value, such
+/// as the number of dropped frames.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub data: u16,
+}
+
+impl Event {
+    pub const fn new(kind: EventKind, data: u16) -> Event {
+        Event { kind, data }
+    }
+
+    /// Packs the event into a non-zero 32 bit value, as zero marks an empty
+    /// slot.
+    fn encode(self) -> u32 {
+        (self.kind as u32) << 16 | self.data as u32
+    }
+
+    fn decode(value: u32) -> Option<Event> {
+        EventKind::from_u8((value >> 16) as u8).map(|kind| Event::new(kind, value as u16))
+    }
+}
+
+/// A ring of the last `N` published events. `N` must be a power of two.
+///
+/// Any number of contexts can publish events, but only a single context
+/// should consume them.
+pub struct EventRing<const N: usize> {
+    slots: [AtomicU32; N],
+    /// Number of events published so far, which wraps around.
+    head: AtomicU32,
+    /// Number of events consumed or overwritten so far, which wraps around.
+    tail: AtomicU32,
+    /// Number of events which were overwritten before being consumed.
+    overwritten: AtomicU32,
+}
+
+impl<const N: usize> EventRing<N> {
+    /// Creates an empty ring.
+    ///
+    /// This is a `const fn`, so the ring can be placed in a `static`.
+    pub const fn new() -> Self {
+        // The event counts wrap around at 2^32, so `N` has to divide 2^32 for
+        // the slot indices to stay contiguous.
+        const { assert!(N.is_power_of_two() && N <= 1 << 31) };
+        EventRing {
+            slots: [const { AtomicU32::new(0) }; N],
+            head: AtomicU32::new(0),
+            tail: AtomicU32::new(0),
+            overwritten: AtomicU32::new(0),
+        }
+    }
+
+    /// Adds `event` to the ring, overwriting the oldest event if the ring is
+    /// full.
+    pub fn publish(&self, event: Event) {
+        #[cfg(target_has_atomic = "32")]
+        let index = self.head.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(not(target_has_atomic = "32"))]
+        let index = {
+            let index = self.head.load(Ordering::Relaxed);
+            self.head.store(index.wrapping_add(1), Ordering::Relaxed);
+            index
+        };
+
+        self.slots[index as usize % N].store(event.encode(), Ordering::Release);
+    }
+
+    /// Removes and returns the oldest event in the ring.
+    ///
+    /// Returns `None` if the ring is empty, or if the oldest event is still
+    /// being published from an interrupted context.
+    pub fn pop(&self) -> Option<Event> {
+        let head = self.head.load(Ordering::Relaxed);
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        let pending = head.wrapping_sub(tail);
+        if pending == 0 {
+            return None;
+        }
+        if pending as usize > N {
+            // The oldest events have been overwritten.
+            let lost = pending - N as u32;
+            self.overwritten.store(
+                self.overwritten.load(Ordering::Relaxed).wrapping_add(lost),
+                Ordering::Relaxed,
+            );
+            tail = tail.wrapping_add(lost);
+        }
+
+        let slot = &self.slots[tail as usize % N];
+
+        #[cfg(target_has_atomic = "32")]
+        let value = slot.swap(0, Ordering::Acquire);
+
+        #[cfg(not(target_has_atomic = "32"))]
+        let value = {
+            let value = slot.load(Ordering::Acquire);
+            slot.store(0, Ordering::Relaxed);
+            value
+        };
+
+        if value == 0 {
+            self.tail.store(tail, Ordering::Relaxed);
+            return None;
+        }
+        self.tail.store(tail.wrapping_add(1), Ordering::Relaxed);
+        Event::decode(value)
+    }
+
+    /// Returns the number of events in the ring, including events which have
+    /// been overwritten but not yet counted as such.
+    pub fn len(&self) -> usize {
+        let head = self.head.load(Ordering::Relaxed);
+        let tail = self.tail.load(Ordering::Relaxed);
+        core::cmp::min(head.wrapping_sub(tail) as usize, N)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of events which were overwritten before they could
+    /// be consumed.
+    ///
+    /// Overwritten events are only counted when the consumer reaches them
+    /// with [`EventRing::pop`].
+    pub fn overwritten(&self) -> u32 {
+        self.overwritten.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Event, EventKind, EventRing};
+    use core::sync::atomic::Ordering;
+
+    #[test]
+    fn test_publish_and_pop() {
+        let ring: EventRing<4> = EventRing::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+
+        ring.publish(Event::new(EventKind::RadioReset, 0));
+        ring.publish(Event::new(EventKind::RxOverflow, 0xFFFF));
+        assert_eq!(ring.len(), 2);
+
+        assert_eq!(ring.pop(), Some(Event::new(EventKind::RadioReset, 0)));
+        assert_eq!(ring.pop(), Some(Event::new(EventKind::RxOverflow, 0xFFFF)));
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.overwritten(), 0);
+    }
+
+    #[test]
+    fn test_all_kinds_round_trip() {
+        let ring: EventRing<8> = EventRing::new();
+        let kinds = [
+            EventKind::RadioReset,
+            EventKind::RxOverflow,
+            EventKind::FlashWriteDeferred,
+            EventKind::BrownoutWarning,
+            EventKind::WatchdogNearExpiry,
+        ];
+        for (i, kind) in kinds.iter().enumerate() {
+            ring.publish(Event::new(*kind, i as u16));
+        }
+        for (i, kind) in kinds.iter().enumerate() {
+            assert_eq!(ring.pop(), Some(Event::new(*kind, i as u16)));
+        }
+    }
+
+    #[test]
+    fn test_overwrite_oldest() {
+        let ring: EventRing<4> = EventRing::new();
+        for i in 0..6 {
+            ring.publish(Event::new(EventKind::FlashWriteDeferred, i));
+        }
+        assert_eq!(ring.len(), 4);
+
+        for i in 2..6 {
+            assert_eq!(
+                ring.pop(),
+                Some(Event::new(EventKind::FlashWriteDeferred, i))
+            );
+        }
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.overwritten(), 2);
+    }
+
+    #[test]
+    fn test_counts_wrap_around() {
+        let ring: EventRing<4> = EventRing::new();
+        ring.head.store(u32::MAX - 1, Ordering::Relaxed);
+        ring.tail.store(u32::MAX - 1, Ordering::Relaxed);
+
+        for i in 0..3 {
+            ring.publish(Event::new(EventKind::BrownoutWarning, i));
+        }
+        assert_eq!(ring.len(), 3);
+        for i in 0..3 {
+            assert_eq!(ring.pop(), Some(Event::new(EventKind::BrownoutWarning, i)));
+        }
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_event_being_published() {
+        let ring: EventRing<4> = EventRing::new();
+        // A publisher has reserved a slot but not yet written the event.
+        ring.head.store(1, Ordering::Relaxed);
+        assert_eq!(ring.pop(), None);
+        assert_eq!(ring.len(), 1);
+
+        ring.slots[0].store(
+            Event::new(EventKind::WatchdogNearExpiry, 1).encode(),
+            Ordering::Relaxed,
+        );
+        assert_eq!(
+            ring.pop(),
+            Some(Event::new(EventKind::WatchdogNearExpiry, 1))
+        );
+    }
+}
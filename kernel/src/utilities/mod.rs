@@ -6,12 +6,14 @@
 
 pub mod binary_write;
 pub mod copy_slice;
+pub mod events;
 pub mod helpers;
 pub mod leasable_buffer;
 pub mod math;
 pub mod mut_imut_buffer;
 pub mod peripheral_management;
 pub mod static_init;
+pub mod statistics;
 pub mod storage_volume;
 
 mod static_ref;
@@ -0,0 +1,173 @@
+// Licensed under the Apache License, Version 2.0 or the MIT License.
+// SPDX-License-Identifier: Apache-2.0 OR MIT
+// Copyright Tock Contributors 2024.
+
+//! Fixed-slot event counters with snapshot-and-reset semantics.
+//!
+//! [`Counters`] holds a fixed number of 32 bit counters, such as dropped
+//! frames or UART overruns, which a subsystem increments and another context
+//! (e.g. the process console or a capsule reporting over the network)
+//! reads. The counters are atomics, so they can be updated from an interrupt
+//! handler and read from the kernel loop without tearing, and without a
+//! critical section.
+//!
+//! On targets without atomic read-modify-write instructions (e.g. ARMv6-M or
+//! RV32 without the A extension), updates are plain loads and stores. They
+//! are only tear-free there if all updates happen outside of interrupt
+//! handlers, which is the case for code running in the kernel loop.
+//!
+//! Usage
+//! -----
+//!
+//! ```rust
+//! use kernel::utilities::statistics::Counters;
+//!
+//! const RX_DROPPED: usize = 0;
+//! const TX_FAILED: usize = 1;
+//!
+//! static RADIO_STATS: Counters<2> = Counters::new();
+//!
+//! RADIO_STATS.increment(RX_DROPPED);
+//! RADIO_STATS.add(TX_FAILED, 3);
+//! assert_eq!(RADIO_STATS.snapshot_and_reset(), [1, 3]);
+//! assert_eq!(RADIO_STATS.get(TX_FAILED), Some(0));
+//! ```
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// A set of `N` event counters, addressed by slot index.
+///
+/// Counters wrap around on overflow. Slot indices outside of `0..N` are
+/// ignored.
+pub struct Counters<const N: usize> {
+    slots: [AtomicU32; N],
+}
+
+impl<const N: usize> Counters<N> {
+    /// Creates `N` counters, all set to zero.
+    ///
+    /// This is a `const fn`, so the counters can be placed in a `static`.
+    pub const fn new() -> Self {
+        Counters {
+            slots: [const { AtomicU32::new(0) }; N],
+        }
+    }
+
+    /// Increments the counter in `slot` by one.
+    pub fn increment(&self, slot: usize) {
+        self.add(slot, 1);
+    }
+
+    /// Adds `count` to the counter in `slot`.
+    pub fn add(&self, slot: usize, count: u32) {
+        if let Some(counter) = self.slots.get(slot) {
+            #[cfg(target_has_atomic = "32")]
+            counter.fetch_add(count, Ordering::Relaxed);
+
+            #[cfg(not(target_has_atomic = "32"))]
+            counter.store(
+                counter.load(Ordering::Relaxed).wrapping_add(count),
+                Ordering::Relaxed,
+            );
+        }
+    }
+
+    /// Returns the current value of the counter in `slot`.
+    pub fn get(&self, slot: usize) -> Option<u32> {
+        self.slots
+            .get(slot)
+            .map(|counter| counter.load(Ordering::Relaxed))
+    }
+
+    /// Returns the current values of all counters.
+    ///
+    /// Each value is read atomically, but the counters are read one after
+    /// another, so the snapshot may include updates to later slots made while
+    /// it was taken.
+    pub fn snapshot(&self) -> [u32; N] {
+        let mut values = [0; N];
+        for (value, counter) in values.iter_mut().zip(self.slots.iter()) {
+            *value = counter.load(Ordering::Relaxed);
+        }
+        values
+    }
+
+    /// Returns the current values of all counters and sets them to zero.
+    ///
+    /// Each counter is read and cleared in one atomic operation, so no update
+    /// is lost between the two snapshots returned by consecutive calls.
+    pub fn snapshot_and_reset(&self) -> [u32; N] {
+        let mut values = [0; N];
+        for (value, counter) in values.iter_mut().zip(self.slots.iter()) {
+            #[cfg(target_has_atomic = "32")]
+            {
+                *value = counter.swap(0, Ordering::Relaxed);
+            }
+
+            #[cfg(not(target_has_atomic = "32"))]
+            {
+                *value = counter.load(Ordering::Relaxed);
+                counter.store(0, Ordering::Relaxed);
+            }
+        }
+        values
+    }
+
+    /// Sets all counters to zero.
+    pub fn reset(&self) {
+        for counter in self.slots.iter() {
+            counter.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Counters;
+
+    #[test]
+    fn test_add_and_get() {
+        let counters: Counters<3> = Counters::new();
+        counters.increment(0);
+        counters.increment(0);
+        counters.add(2, 5);
+
+        assert_eq!(counters.get(0), Some(2));
+        assert_eq!(counters.get(1), Some(0));
+        assert_eq!(counters.get(2), Some(5));
+        assert_eq!(counters.get(3), None);
+        assert_eq!(counters.snapshot(), [2, 0, 5]);
+    }
+
+    #[test]
+    fn test_out_of_range_slot() {
+        let counters: Counters<1> = Counters::new();
+        counters.increment(1);
+        assert_eq!(counters.snapshot(), [0]);
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let counters: Counters<1> = Counters::new();
+        counters.add(0, u32::MAX);
+        counters.add(0, 2);
+        assert_eq!(counters.get(0), Some(1));
+    }
+
+    #[test]
+    fn test_snapshot_and_reset() {
+        let counters: Counters<2> = Counters::new();
+        counters.add(0, 7);
+        counters.increment(1);
+
+        assert_eq!(counters.snapshot_and_reset(), [7, 1]);
+        assert_eq!(counters.snapshot(), [0, 0]);
+
+        counters.increment(1);
+        assert_eq!(counters.snapshot_and_reset(), [0, 1]);
+
+        counters.add(0, 3);
+        counters.reset();
+        assert_eq!(counters.snapshot(), [0, 0]);
+    }
+}